@@ -1,8 +1,11 @@
+use std::{collections::BTreeMap, num::NonZeroUsize};
+
 use indoc::indoc;
+use lookup::{lookup_v2::ConfigValuePath, owned_value_path};
 use tower::ServiceBuilder;
 use vector_config::configurable_component;
 use vector_core::config::proxy::ProxyConfig;
-use vector_core::schema;
+use vector_core::{schema, stream::BatcherSettings};
 use vrl::value::Kind;
 
 use crate::{
@@ -12,23 +15,138 @@ use crate::{
     sinks::{
         datadog::{
             events::{
+                request_builder::DatadogEventsRequestBuilder,
                 service::{DatadogEventsResponse, DatadogEventsService},
-                sink::DatadogEventsSink,
+                sink::{DatadogEventsSink, EventFields},
             },
             get_api_base_endpoint, DatadogCommonConfig,
         },
-        util::{http::HttpStatusRetryLogic, ServiceBuilderExt, TowerRequestConfig},
+        util::{
+            http::HttpStatusRetryLogic, BatchConfig, Compression, ServiceBuilderExt,
+            SinkBatchSettings, TowerRequestConfig,
+        },
         Healthcheck, VectorSink,
     },
     tls::MaybeTlsSettings,
 };
 
+/// Datadog Events API version.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatadogEventsApiVersion {
+    /// Events are sent to the `/api/v1/events` endpoint, one event per request.
+    #[default]
+    V1,
+
+    /// Events are sent to the `/api/v2/events` endpoint, in batches encoded as a JSON array.
+    V2,
+}
+
+impl DatadogEventsApiVersion {
+    const fn path(self) -> &'static str {
+        match self {
+            Self::V1 => "api/v1/events",
+            Self::V2 => "api/v2/events",
+        }
+    }
+}
+
+/// How events missing a `title` or `text` are handled.
+///
+/// The Datadog Events API rejects events without them.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatadogEventsMissingFieldPolicy {
+    /// Events are dropped.
+    #[default]
+    Drop,
+
+    /// The missing fields are set to `default_title` and `default_text`.
+    Fill,
+}
+
+/// How events with fields that aren't sent to Datadog are handled.
+///
+/// Only the fields the Datadog Events API defines, and `additional_fields`, are sent.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatadogEventsUnknownFieldPolicy {
+    /// The fields are removed from events.
+    #[default]
+    Strip,
+
+    /// The fields are removed from events, and a warning naming them is logged.
+    Warn,
+
+    /// Events are dropped, and a warning naming the fields is logged.
+    Reject,
+}
+
+/// The priority of Datadog events.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatadogEventsPriority {
+    /// Normal priority.
+    #[default]
+    Normal,
+
+    /// Low priority.
+    Low,
+}
+
+impl DatadogEventsPriority {
+    pub(super) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Low => "low",
+        }
+    }
+}
+
+/// The serialization format of the body of requests.
+#[configurable_component]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatadogEventsSerializer {
+    /// Events are serialized as JSON.
+    #[default]
+    Json,
+
+    /// Events are serialized as [MessagePack][msgpack].
+    ///
+    /// [msgpack]: https://msgpack.org/
+    Msgpack,
+}
+
+impl DatadogEventsSerializer {
+    pub(super) const fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Msgpack => "application/msgpack",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DatadogEventsDefaultBatchSettings;
+
+impl SinkBatchSettings for DatadogEventsDefaultBatchSettings {
+    const MAX_EVENTS: Option<usize> = Some(100);
+    const MAX_BYTES: Option<usize> = Some(1_000_000);
+    const TIMEOUT_SECS: f64 = 1.0;
+}
+
 /// Configuration for the `datadog_events` sink.
 #[configurable_component(sink(
     "datadog_events",
     "Publish observability events to the Datadog Events API."
 ))]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Derivative)]
+#[derivative(Default)]
 #[serde(deny_unknown_fields)]
 pub struct DatadogEventsConfig {
     #[serde(flatten)]
@@ -39,11 +157,157 @@ pub struct DatadogEventsConfig {
     #[serde(default)]
     pub region: Option<Region>,
 
+    #[configurable(derived)]
+    #[serde(default)]
+    pub api_version: DatadogEventsApiVersion,
+
+    /// Event batching behavior.
+    ///
+    /// Only used with version `v2` of the API, as version `v1` accepts a single event per request.
+    #[serde(default)]
+    pub batch: BatchConfig<DatadogEventsDefaultBatchSettings>,
+
+    /// The maximum number of events sent in a single request, with version `v2` of the API.
+    ///
+    /// Batches of more events are split into several requests.
+    #[configurable(metadata(docs::type_unit = "events"))]
+    #[configurable(metadata(docs::examples = 100))]
+    pub max_events_per_request: Option<NonZeroUsize>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub compression: Compression,
+
+    /// The minimum size of the encoded payload of a request for it to be compressed.
+    ///
+    /// Smaller requests are sent uncompressed, even when `compression` is enabled, as compressing
+    /// them costs more than it saves.
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 1024))]
+    pub min_compression_bytes: Option<usize>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub serializer: DatadogEventsSerializer,
+
+    /// Fields renamed before the sink completes the fields of events and removes the ones that
+    /// aren't sent to Datadog, such as to send a `subject` field as the `title` of events.
+    ///
+    /// Fields and their new names are paths, so `meta.title` renames the `title` field nested in
+    /// `meta`. A field isn't renamed if the event already has a field with its new name.
+    #[serde(default)]
+    #[configurable(metadata(docs::additional_props_description = "A field and its new name."))]
+    #[configurable(metadata(docs::examples = "rename_fields_examples()"))]
+    pub rename_fields: BTreeMap<String, String>,
+
+    /// Fields sent to Datadog, in addition to the ones the Events API defines.
+    ///
+    /// Any other field is removed from events before they are sent.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "custom_attribute", docs::examples = "team.name"))]
+    pub additional_fields: Vec<ConfigValuePath>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub unknown_field_policy: DatadogEventsUnknownFieldPolicy,
+
+    /// The field holding the Datadog API key of events.
+    ///
+    /// When present, it takes precedence over the API key set in the metadata of events, and over
+    /// `default_api_key`.
+    #[serde(default = "default_api_key_field")]
+    #[derivative(Default(value = "default_api_key_field()"))]
+    #[configurable(metadata(docs::examples = "dd_api_key"))]
+    pub api_key_field: ConfigValuePath,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub missing_field_policy: DatadogEventsMissingFieldPolicy,
+
+    /// The `title` set on events that don't have one, when `missing_field_policy` is `fill`.
+    #[serde(default = "default_title")]
+    #[derivative(Default(value = "default_title()"))]
+    #[configurable(metadata(docs::examples = "Deployment"))]
+    pub default_title: String,
+
+    /// The `text` set on events that have neither a `text` nor a message, when
+    /// `missing_field_policy` is `fill`.
+    #[serde(default = "default_text")]
+    #[derivative(Default(value = "default_text()"))]
+    #[configurable(metadata(docs::examples = "(no text)"))]
+    pub default_text: String,
+
+    /// The maximum size of the encoded payload of a request, before compression.
+    ///
+    /// Requests over it are dropped. With version `v1` of the API, requests carry a single event,
+    /// so this limits the size of events.
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 65536))]
+    pub max_payload_bytes: Option<usize>,
+
+    /// The maximum length of the `aggregation_key` of events, in characters.
+    ///
+    /// Longer keys are truncated, as Datadog doesn't accept them.
+    #[serde(default = "default_max_aggregation_key_length")]
+    #[derivative(Default(value = "default_max_aggregation_key_length()"))]
+    pub max_aggregation_key_length: usize,
+
+    /// Whether to wrap the `text` of events in `%%%` markers, for Datadog to render it as Markdown.
+    #[serde(default)]
+    pub markdown: bool,
+
+    /// The `host` set on events that don't have one.
+    #[configurable(metadata(docs::examples = "my-host"))]
+    pub default_host: Option<String>,
+
+    /// The `source_type_name` set on events that don't have one.
+    #[configurable(metadata(docs::examples = "vector"))]
+    pub default_source_type_name: Option<String>,
+
+    /// Whether events that don't have a `host` get the hostname of the machine Vector runs on,
+    /// when `default_host` isn't set.
+    #[serde(default)]
+    pub default_to_hostname: bool,
+
+    /// The field holding the timestamp of events, which is sent as their `date_happened` when they
+    /// don't have one.
+    ///
+    /// By default, the field with the `timestamp` meaning is used.
+    #[configurable(metadata(docs::examples = "time"))]
+    pub timestamp_field: Option<ConfigValuePath>,
+
+    /// The `priority` set on events whose `priority` isn't one the Datadog Events API accepts.
+    #[serde(default)]
+    pub default_priority: DatadogEventsPriority,
+
     #[configurable(derived)]
     #[serde(default)]
     pub request: TowerRequestConfig,
 }
 
+fn rename_fields_examples() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("description".to_owned(), "text".to_owned()),
+        ("subject".to_owned(), "title".to_owned()),
+    ])
+}
+
+fn default_title() -> String {
+    "Vector event".to_owned()
+}
+
+fn default_text() -> String {
+    "No text".to_owned()
+}
+
+fn default_api_key_field() -> ConfigValuePath {
+    ConfigValuePath(owned_value_path!("_dd_api_key"))
+}
+
+const fn default_max_aggregation_key_length() -> usize {
+    100
+}
+
 impl GenerateConfig for DatadogEventsConfig {
     fn generate_config() -> toml::Value {
         toml::from_str(indoc! {r#"
@@ -61,7 +325,8 @@ impl DatadogEventsConfig {
         );
 
         // We know this URI will be valid since we have just built it up ourselves.
-        http::Uri::try_from(format!("{}/api/v1/events", api_base_endpoint)).expect("URI not valid")
+        http::Uri::try_from(format!("{}/{}", api_base_endpoint, self.api_version.path()))
+            .expect("URI not valid")
     }
 
     fn build_client(&self, proxy: &ProxyConfig) -> crate::Result<HttpClient> {
@@ -70,6 +335,23 @@ impl DatadogEventsConfig {
         Ok(client)
     }
 
+    fn batch_settings(&self) -> crate::Result<BatcherSettings> {
+        let mut batch_settings = self.batch.into_batcher_settings()?;
+        match self.api_version {
+            DatadogEventsApiVersion::V1 => {
+                // The v1 endpoint only accepts a single event per request.
+                batch_settings.item_limit = 1;
+                if self.max_events_per_request.is_some() {
+                    warn!(
+                        message = "Option `max_events_per_request` is ignored with version `v1` of the Events API."
+                    );
+                }
+            }
+            DatadogEventsApiVersion::V2 => (),
+        }
+        Ok(batch_settings)
+    }
+
     fn build_sink(&self, client: HttpClient) -> crate::Result<VectorSink> {
         let service = DatadogEventsService::new(
             self.get_api_events_endpoint(),
@@ -85,7 +367,13 @@ impl DatadogEventsConfig {
             .settings(request_settings, retry_logic)
             .service(service);
 
-        let sink = DatadogEventsSink { service };
+        let sink = DatadogEventsSink {
+            service,
+            fields: EventFields::new(self)?,
+            request_builder: DatadogEventsRequestBuilder::new(self),
+            batch_settings: self.batch_settings()?,
+            max_events_per_request: self.max_events_per_request,
+        };
 
         Ok(VectorSink::from_event_streamsink(sink))
     }
@@ -120,10 +408,84 @@ impl SinkConfig for DatadogEventsConfig {
 
 #[cfg(test)]
 mod tests {
-    use crate::sinks::datadog::events::config::DatadogEventsConfig;
+    use std::num::NonZeroUsize;
+
+    use crate::{
+        common::datadog::Region,
+        sinks::datadog::events::config::{DatadogEventsApiVersion, DatadogEventsConfig},
+    };
 
     #[test]
     fn generate_config() {
         crate::test_util::test_generate_config::<DatadogEventsConfig>();
     }
+
+    #[test]
+    fn api_version_endpoint() {
+        let config = DatadogEventsConfig::default();
+        assert_eq!(
+            config.get_api_events_endpoint(),
+            "https://api.datadoghq.com/api/v1/events"
+        );
+
+        let config = DatadogEventsConfig {
+            api_version: DatadogEventsApiVersion::V2,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_api_events_endpoint(),
+            "https://api.datadoghq.com/api/v2/events"
+        );
+    }
+
+    #[test]
+    fn site_endpoint() {
+        for (site, endpoint) in [
+            ("datadoghq.com", "https://api.datadoghq.com/api/v1/events"),
+            (
+                "us3.datadoghq.com",
+                "https://api.us3.datadoghq.com/api/v1/events",
+            ),
+            ("datadoghq.eu", "https://api.datadoghq.eu/api/v1/events"),
+            ("ddog-gov.com", "https://api.ddog-gov.com/api/v1/events"),
+        ] {
+            let mut config = DatadogEventsConfig::default();
+            config.dd_common.site = site.to_owned();
+            assert_eq!(config.get_api_events_endpoint(), endpoint, "{}", site);
+
+            // An explicit endpoint takes precedence over the site
+            config.dd_common.endpoint = Some("http://localhost:8080".to_owned());
+            assert_eq!(
+                config.get_api_events_endpoint(),
+                "http://localhost:8080/api/v1/events",
+                "{}",
+                site
+            );
+        }
+
+        // So does the deprecated region
+        let mut config = DatadogEventsConfig {
+            region: Some(Region::Eu),
+            ..Default::default()
+        };
+        config.dd_common.site = "ddog-gov.com".to_owned();
+        assert_eq!(
+            config.get_api_events_endpoint(),
+            "https://api.datadoghq.eu/api/v1/events"
+        );
+    }
+
+    #[test]
+    fn api_version_batch_settings() {
+        // Version v1 accepts a single event per request, whatever the batch settings are
+        let mut config = DatadogEventsConfig {
+            max_events_per_request: NonZeroUsize::new(10),
+            ..Default::default()
+        };
+        config.batch.max_events = Some(50);
+        assert_eq!(config.batch_settings().unwrap().item_limit, 1);
+
+        config.api_version = DatadogEventsApiVersion::V2;
+        assert_eq!(config.batch_settings().unwrap().item_limit, 50);
+    }
 }
@@ -1,23 +1,36 @@
 use std::{io, sync::Arc};
 
 use bytes::Bytes;
-use codecs::JsonSerializerConfig;
-use lookup::lookup_v2::ConfigValuePath;
-use vector_common::request_metadata::{MetaDescriptive, RequestMetadata};
-use vector_core::ByteSizeOf;
+use codecs::{encoding::Framer, BytesEncoder, CharacterDelimitedEncoder, JsonSerializerConfig};
+use lookup::{event_path, lookup_v2::ConfigValuePath};
+use snafu::Snafu;
+use vector_common::{
+    internal_event::{ComponentEventsDropped, UNINTENTIONAL},
+    request_metadata::{GroupedCountByteSize, MetaDescriptive, RequestMetadata},
+};
+use vector_core::{config::telemetry, ByteSizeOf, EstimatedJsonEncodedSizeOf};
 
 use crate::{
     codecs::{Encoder, TimestampFormat, Transformer},
-    event::{Event, EventFinalizers, Finalizable},
-    sinks::util::{
-        metadata::RequestMetadataBuilder, request_builder::EncodeResult, Compression, ElementCount,
-        RequestBuilder,
+    event::{Event, EventFinalizers, Finalizable, LogEvent},
+    sinks::{
+        datadog::events::config::{
+            DatadogEventsApiVersion, DatadogEventsConfig, DatadogEventsSerializer,
+        },
+        util::{
+            encoding::{self, write_all, Encoder as _},
+            metadata::RequestMetadataBuilder,
+            request_builder::EncodeResult,
+            Compression, Compressor, ElementCount, RequestBuilder,
+        },
     },
 };
 
 #[derive(Clone)]
 pub struct DatadogEventsRequest {
     pub body: Bytes,
+    pub compression: Compression,
+    pub content_type: &'static str,
     pub metadata: Metadata,
     request_metadata: RequestMetadata,
 }
@@ -36,8 +49,7 @@ impl ByteSizeOf for DatadogEventsRequest {
 
 impl ElementCount for DatadogEventsRequest {
     fn element_count(&self) -> usize {
-        // Datadog Events api only accepts a single event per request
-        1
+        self.metadata.event_count
     }
 }
 
@@ -55,50 +67,234 @@ impl MetaDescriptive for DatadogEventsRequest {
 pub struct Metadata {
     pub finalizers: EventFinalizers,
     pub api_key: Option<Arc<str>>,
+    pub event_count: usize,
+}
+
+#[derive(Debug, Snafu)]
+pub enum RequestBuildError {
+    #[snafu(display(
+        "Encoded payload of {} bytes is greater than the max limit of {} bytes.",
+        size,
+        max_size
+    ))]
+    PayloadTooBig { size: usize, max_size: usize },
+    #[snafu(display("Failed to build payload with error: {}", error))]
+    Io { error: io::Error },
+}
+
+impl From<io::Error> for RequestBuildError {
+    fn from(error: io::Error) -> RequestBuildError {
+        RequestBuildError::Io { error }
+    }
+}
+
+/// A reason for the Datadog Events API to reject an event.
+#[derive(Debug, Eq, PartialEq, Snafu)]
+pub enum FieldError {
+    #[snafu(display("Only logs can be sent as Datadog events."))]
+    NotALog,
+    #[snafu(display("Required field `{}` is missing.", field))]
+    Missing { field: &'static str },
+    #[snafu(display("Field `{}` isn't sent to Datadog.", field))]
+    NotAllowed { field: String },
+    #[snafu(display("Field `{}` has unsupported value `{}`.", field, value))]
+    UnsupportedValue { field: &'static str, value: String },
+}
+
+pub struct DatadogEventsEncoder {
+    json: (Transformer, Encoder<Framer>),
+    serializer: DatadogEventsSerializer,
+    api_version: DatadogEventsApiVersion,
+}
+
+impl encoding::Encoder<Vec<Event>> for DatadogEventsEncoder {
+    fn encode_input(
+        &self,
+        events: Vec<Event>,
+        writer: &mut dyn io::Write,
+    ) -> io::Result<(usize, GroupedCountByteSize)> {
+        if self.serializer == DatadogEventsSerializer::Json {
+            return self.json.encode_input(events, writer);
+        }
+
+        let n_events = events.len();
+        let mut byte_size = telemetry().create_request_count_byte_size();
+        let logs = events
+            .into_iter()
+            .map(|mut event| {
+                self.json.0.transform(&mut event);
+                byte_size.add_event(&event, event.estimated_json_encoded_size_of());
+                event.into_log()
+            })
+            .collect::<Vec<LogEvent>>();
+
+        let body = match self.api_version {
+            // Batches only ever hold a single event, which is sent as it is.
+            DatadogEventsApiVersion::V1 => logs.first().map(rmp_serde::to_vec_named),
+            // Batches of events are sent as an array.
+            DatadogEventsApiVersion::V2 => Some(rmp_serde::to_vec_named(&logs)),
+        }
+        .transpose()
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?
+        .unwrap_or_default();
+        write_all(writer, n_events, &body)?;
+
+        Ok((body.len(), byte_size))
+    }
 }
 
 pub struct DatadogEventsRequestBuilder {
-    encoder: (Transformer, Encoder<()>),
+    encoder: DatadogEventsEncoder,
+    compression: Compression,
+    min_compression_bytes: Option<usize>,
+    max_payload_bytes: Option<usize>,
 }
 
 impl Default for DatadogEventsRequestBuilder {
     fn default() -> Self {
-        Self::new()
+        Self::new(&DatadogEventsConfig::default())
     }
 }
 
 impl DatadogEventsRequestBuilder {
-    pub fn new() -> DatadogEventsRequestBuilder {
-        DatadogEventsRequestBuilder { encoder: encoder() }
+    pub fn new(config: &DatadogEventsConfig) -> DatadogEventsRequestBuilder {
+        DatadogEventsRequestBuilder {
+            encoder: DatadogEventsEncoder {
+                json: encoder(config.api_version, &config.additional_fields),
+                serializer: config.serializer,
+                api_version: config.api_version,
+            },
+            compression: config.compression,
+            min_compression_bytes: config.min_compression_bytes,
+            max_payload_bytes: config.max_payload_bytes,
+        }
     }
 }
 
-impl RequestBuilder<Event> for DatadogEventsRequestBuilder {
+impl DatadogEventsRequestBuilder {
+    /// Checks whether the Datadog Events API accepts the event as it is, before the sink completes
+    /// its fields, returning all of the problems found.
+    pub fn validate(&self, event: &Event) -> Result<(), Vec<FieldError>> {
+        let Some(log) = event.maybe_as_log() else {
+            return Err(vec![FieldError::NotALog]);
+        };
+        let mut errors = Vec::new();
+
+        if !log.contains(event_path!("title")) {
+            errors.push(FieldError::Missing { field: "title" });
+        }
+        if !log.contains(event_path!("text")) {
+            errors.push(FieldError::Missing { field: "text" });
+        }
+
+        let allowed_fields = self
+            .encoder
+            .json
+            .0
+            .only_fields()
+            .as_deref()
+            .unwrap_or_default();
+        errors.extend(
+            disallowed_fields(log, allowed_fields)
+                .into_iter()
+                .map(|field| FieldError::NotAllowed { field }),
+        );
+
+        for (field, accepted) in [
+            ("alert_type", &["error", "warning", "info", "success"][..]),
+            ("priority", &["normal", "low"][..]),
+        ] {
+            if let Some(value) = log.get(event_path!(field)) {
+                let value = value.to_string_lossy();
+                if !accepted.contains(&value.as_ref()) {
+                    errors.push(FieldError::UnsupportedValue {
+                        field,
+                        value: value.into_owned(),
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl RequestBuilder<(Option<Arc<str>>, Vec<Event>)> for DatadogEventsRequestBuilder {
     type Metadata = Metadata;
-    type Events = Event;
-    type Encoder = (Transformer, Encoder<()>);
+    type Events = Vec<Event>;
+    type Encoder = DatadogEventsEncoder;
     type Payload = Bytes;
     type Request = DatadogEventsRequest;
-    type Error = io::Error;
+    type Error = RequestBuildError;
 
     fn compression(&self) -> Compression {
-        Compression::None
+        self.compression
     }
 
     fn encoder(&self) -> &Self::Encoder {
         &self.encoder
     }
 
-    fn split_input(&self, event: Event) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
-        let builder = RequestMetadataBuilder::from_event(&event);
+    fn split_input(
+        &self,
+        input: (Option<Arc<str>>, Vec<Event>),
+    ) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
+        let (api_key, mut events) = input;
+        let builder = RequestMetadataBuilder::from_events(&events);
 
-        let mut log = event.into_log();
         let metadata = Metadata {
-            finalizers: log.take_finalizers(),
-            api_key: log.metadata_mut().datadog_api_key(),
+            finalizers: events.take_finalizers(),
+            api_key,
+            event_count: events.len(),
         };
 
-        (metadata, builder, Event::from(log))
+        (metadata, builder, events)
+    }
+
+    fn encode_events(
+        &self,
+        events: Self::Events,
+    ) -> Result<EncodeResult<Self::Payload>, Self::Error> {
+        // The payload is serialized separately first, to check its size before compression.
+        let mut buf = Vec::new();
+        let n_events = events.len();
+        let (uncompressed_size, byte_size) = self.encoder().encode_input(events, &mut buf)?;
+        if let Some(max_size) = self.max_payload_bytes {
+            if uncompressed_size > max_size {
+                emit!(ComponentEventsDropped::<UNINTENTIONAL> {
+                    count: n_events,
+                    reason: "Encoded payload is greater than the max limit."
+                });
+                return Err(RequestBuildError::PayloadTooBig {
+                    size: uncompressed_size,
+                    max_size,
+                });
+            }
+        }
+
+        // Small payloads are sent as they are, as compressing them costs more than it saves.
+        let compression = match self.min_compression_bytes {
+            Some(min_size) if uncompressed_size < min_size => Compression::None,
+            _ => self.compression,
+        };
+
+        let mut compressor = Compressor::from(compression);
+        write_all(&mut compressor, n_events, &buf)?;
+        let bytes = compressor.into_inner().freeze();
+
+        if compression.is_compressed() {
+            Ok(EncodeResult::compressed(
+                bytes,
+                uncompressed_size,
+                byte_size,
+            ))
+        } else {
+            Ok(EncodeResult::uncompressed(bytes, byte_size))
+        }
     }
 
     fn build_request(
@@ -107,41 +303,90 @@ impl RequestBuilder<Event> for DatadogEventsRequestBuilder {
         request_metadata: RequestMetadata,
         payload: EncodeResult<Self::Payload>,
     ) -> Self::Request {
+        // The payload is only compressed when it is large enough.
+        let compression = if payload.compressed_byte_size.is_some() {
+            self.compression
+        } else {
+            Compression::None
+        };
+
         DatadogEventsRequest {
             body: payload.into_payload(),
+            compression,
+            content_type: self.encoder.serializer.content_type(),
             metadata,
             request_metadata,
         }
     }
 }
 
-fn encoder() -> (Transformer, Encoder<()>) {
+/// Returns the fields sent to Datadog, which are the ones the Events API defines along with
+/// `additional_fields`.
+pub(super) fn allowed_fields(additional_fields: &[ConfigValuePath]) -> Vec<ConfigValuePath> {
     // DataDog Event API allows only some fields, and refuses
     // to accept event if it contains any other field.
-    let only_fields = Some(
-        [
-            "aggregation_key",
-            "alert_type",
-            "date_happened",
-            "device_name",
-            "host",
-            "priority",
-            "related_event_id",
-            "source_type_name",
-            "tags",
-            "text",
-            "title",
-        ]
+    let mut only_fields: Vec<_> = [
+        "aggregation_key",
+        "alert_type",
+        "date_happened",
+        "device_name",
+        "host",
+        "priority",
+        "related_event_id",
+        "source_type_name",
+        "tags",
+        "text",
+        "title",
+    ]
+    .iter()
+    .map(|field| ConfigValuePath::try_from((*field).to_string()).unwrap())
+    .collect();
+    for field in additional_fields {
+        if !only_fields.contains(field) {
+            only_fields.push(field.clone());
+        }
+    }
+    only_fields
+}
+
+/// Returns the fields of the log that aren't sent to Datadog, as they aren't allowed.
+pub(super) fn disallowed_fields(log: &LogEvent, allowed_fields: &[ConfigValuePath]) -> Vec<String> {
+    let allowed_fields: Vec<String> = allowed_fields
         .iter()
-        .map(|field| ConfigValuePath::try_from((*field).to_string()).unwrap())
-        .collect(),
-    );
+        .map(|field| field.0.to_string())
+        .collect();
+    log.convert_to_fields()
+        .map(|(field, _)| field)
+        .filter(|field| {
+            // Nested fields are sent along with the allowed field holding them
+            !allowed_fields.iter().any(|allowed| {
+                field.strip_prefix(allowed.as_str()).map_or(false, |rest| {
+                    rest.is_empty() || rest.starts_with('.') || rest.starts_with('[')
+                })
+            })
+        })
+        .collect()
+}
+
+fn encoder(
+    api_version: DatadogEventsApiVersion,
+    additional_fields: &[ConfigValuePath],
+) -> (Transformer, Encoder<Framer>) {
+    let only_fields = allowed_fields(additional_fields);
     // DataDog Event API requires unix timestamp.
     let timestamp_format = Some(TimestampFormat::Unix);
 
     (
-        Transformer::new(only_fields, None, timestamp_format)
+        Transformer::new(Some(only_fields), None, timestamp_format)
             .expect("transformer configuration must be valid"),
-        Encoder::<()>::new(JsonSerializerConfig::default().build().into()),
+        Encoder::<Framer>::new(
+            match api_version {
+                // Batches only ever hold a single event, which is sent as it is.
+                DatadogEventsApiVersion::V1 => BytesEncoder::new().into(),
+                // Batches of events are sent as a JSON array.
+                DatadogEventsApiVersion::V2 => CharacterDelimitedEncoder::new(b',').into(),
+            },
+            JsonSerializerConfig::default().build().into(),
+        ),
     )
 }
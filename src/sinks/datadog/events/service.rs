@@ -64,12 +64,14 @@ impl DatadogEventsService {
                 None => default_api_key.as_str(),
             };
 
-            let request = Request::post(&endpoint)
-                .header("Content-Type", "application/json")
+            let mut request = Request::post(&endpoint)
+                .header("Content-Type", req.content_type)
                 .header("DD-API-KEY", api_key)
-                .header("Content-Length", req.body.len())
-                .body(req.body)
-                .map_err(|x| x.into());
+                .header("Content-Length", req.body.len());
+            if let Some(content_encoding) = req.compression.content_encoding() {
+                request = request.header("Content-Encoding", content_encoding);
+            }
+            let request = request.body(req.body).map_err(|x| x.into());
             future::ready(request)
         });
 
@@ -1,17 +1,46 @@
-use std::fmt;
+use std::{fmt, num::NonZeroUsize, sync::Arc};
 
-use lookup::event_path;
+use futures::stream;
+
+use lookup::{event_path, lookup_v2::ConfigValuePath, metadata_path, OwnedTargetPath, PathPrefix};
+
+use vector_common::internal_event::{ComponentEventsDropped, INTENTIONAL};
 
 use crate::{
     internal_events::{ParserMissingFieldError, DROP_EVENT},
     sinks::{
-        datadog::events::request_builder::{DatadogEventsRequest, DatadogEventsRequestBuilder},
+        datadog::events::{
+            config::{
+                DatadogEventsConfig, DatadogEventsMissingFieldPolicy, DatadogEventsPriority,
+                DatadogEventsUnknownFieldPolicy,
+            },
+            request_builder::{
+                allowed_fields, disallowed_fields, DatadogEventsRequest,
+                DatadogEventsRequestBuilder, RequestBuildError,
+            },
+        },
         prelude::*,
     },
 };
 
+#[derive(Default)]
+struct EventPartitioner;
+
+impl Partitioner for EventPartitioner {
+    type Item = Event;
+    type Key = Option<Arc<str>>;
+
+    fn partition(&self, item: &Self::Item) -> Self::Key {
+        item.metadata().datadog_api_key()
+    }
+}
+
 pub struct DatadogEventsSink<S> {
     pub(super) service: S,
+    pub(super) fields: EventFields,
+    pub(super) request_builder: DatadogEventsRequestBuilder,
+    pub(super) batch_settings: BatcherSettings,
+    pub(super) max_events_per_request: Option<NonZeroUsize>,
 }
 
 impl<S> DatadogEventsSink<S>
@@ -22,14 +51,22 @@ where
     S::Error: fmt::Debug + Into<crate::Error> + Send,
 {
     async fn run(self: Box<Self>, input: BoxStream<'_, Event>) -> Result<(), ()> {
+        let batch_settings = self.batch_settings;
+        let fields = self.fields;
+        let max_events_per_request = self.max_events_per_request;
+
         input
-            .filter_map(ensure_required_fields)
+            .filter_map(move |event| future::ready(fields.ensure_required_fields(event)))
+            .batched_partitioned(EventPartitioner, || batch_settings.as_byte_size_config())
+            .flat_map(move |batch| stream::iter(split_batch(batch, max_events_per_request)))
             .request_builder(
                 default_request_builder_concurrency_limit(),
-                DatadogEventsRequestBuilder::new(),
+                self.request_builder,
             )
             .filter_map(|request| async move {
                 match request {
+                    // The request builder already reported the events as dropped
+                    Err(RequestBuildError::PayloadTooBig { .. }) => None,
                     Err(error) => {
                         emit!(SinkRequestBuildError { error });
                         None
@@ -43,41 +80,320 @@ where
     }
 }
 
-async fn ensure_required_fields(event: Event) -> Option<Event> {
-    let mut log = event.into_log();
+/// Splits a batch of events into batches of at most `max_events` events, which are each sent in a
+/// request of their own.
+///
+/// The finalizers of events stay with them, so each request only finalizes the events it holds.
+pub(super) fn split_batch(
+    batch: (Option<Arc<str>>, Vec<Event>),
+    max_events: Option<NonZeroUsize>,
+) -> Vec<(Option<Arc<str>>, Vec<Event>)> {
+    let (api_key, mut events) = batch;
+    let Some(max_events) = max_events.map(NonZeroUsize::get) else {
+        return vec![(api_key, events)];
+    };
 
-    if !log.contains(event_path!("title")) {
-        emit!(ParserMissingFieldError::<DROP_EVENT> { field: "title" });
-        return None;
+    let mut batches = Vec::new();
+    while events.len() > max_events {
+        let rest = events.split_off(max_events);
+        batches.push((api_key.clone(), std::mem::replace(&mut events, rest)));
     }
+    batches.push((api_key, events));
+    batches
+}
+
+fn rename_field_path(field: &str) -> crate::Result<ConfigValuePath> {
+    ConfigValuePath::try_from(field.to_owned())
+        .map_err(|error| format!("Invalid field in `rename_fields`: {}", error).into())
+}
+
+/// Completes the fields of events that the Datadog Events API expects.
+#[derive(Clone, Debug)]
+pub(super) struct EventFields {
+    missing_field_policy: DatadogEventsMissingFieldPolicy,
+    default_title: String,
+    default_text: String,
+    default_priority: DatadogEventsPriority,
+    timestamp_field: Option<ConfigValuePath>,
+    default_host: Option<String>,
+    default_source_type_name: Option<String>,
+    markdown: bool,
+    max_aggregation_key_length: usize,
+    api_key_field: ConfigValuePath,
+    rename_fields: Vec<(ConfigValuePath, ConfigValuePath)>,
+    unknown_field_policy: DatadogEventsUnknownFieldPolicy,
+    allowed_fields: Vec<ConfigValuePath>,
+}
+
+impl EventFields {
+    /// Fails if a field `rename_fields` renames, or its new name, isn't a valid path.
+    pub(super) fn new(config: &DatadogEventsConfig) -> crate::Result<Self> {
+        let rename_fields = config
+            .rename_fields
+            .iter()
+            .map(|(field, new_field)| {
+                Ok((rename_field_path(field)?, rename_field_path(new_field)?))
+            })
+            .collect::<crate::Result<_>>()?;
 
-    if !log.contains(event_path!("text")) {
-        let message_path = log
-            .message_path()
-            .expect("message is required (make sure the \"message\" semantic meaning is set)")
-            .clone();
-        log.rename_key(&message_path, event_path!("text"));
+        Ok(Self {
+            missing_field_policy: config.missing_field_policy,
+            default_title: config.default_title.clone(),
+            default_text: config.default_text.clone(),
+            default_priority: config.default_priority,
+            timestamp_field: config.timestamp_field.clone(),
+            default_host: config.default_host.clone().or_else(|| {
+                config
+                    .default_to_hostname
+                    .then(crate::get_hostname)
+                    .and_then(Result::ok)
+            }),
+            default_source_type_name: config.default_source_type_name.clone(),
+            markdown: config.markdown,
+            max_aggregation_key_length: config.max_aggregation_key_length,
+            api_key_field: config.api_key_field.clone(),
+            rename_fields,
+            unknown_field_policy: config.unknown_field_policy,
+            allowed_fields: allowed_fields(&config.additional_fields),
+        })
     }
 
-    if !log.contains(event_path!("host")) {
-        if let Some(host_path) = log.host_path().cloned().as_ref() {
-            log.rename_key(host_path, event_path!("host"));
+    pub(super) fn ensure_required_fields(&self, event: Event) -> Option<Event> {
+        let mut log = event.into_log();
+
+        // The API key is taken from the metadata when partitioning events
+        if let Some(api_key) = log.remove((PathPrefix::Event, &self.api_key_field)) {
+            log.metadata_mut()
+                .set_datadog_api_key(Arc::from(api_key.to_string_lossy().as_ref()));
+        }
+
+        for (field, new_field) in &self.rename_fields {
+            if !log.contains((PathPrefix::Event, new_field)) {
+                log.rename_key((PathPrefix::Event, field), (PathPrefix::Event, new_field));
+            }
+        }
+
+        if !log.contains(event_path!("title")) {
+            match self.missing_field_policy {
+                DatadogEventsMissingFieldPolicy::Drop => {
+                    emit!(ParserMissingFieldError::<DROP_EVENT> { field: "title" });
+                    return None;
+                }
+                DatadogEventsMissingFieldPolicy::Fill => {
+                    log.insert(event_path!("title"), self.default_title.clone());
+                }
+            }
         }
-    }
 
-    if !log.contains(event_path!("date_happened")) {
-        if let Some(timestamp_path) = log.timestamp_path().cloned().as_ref() {
-            log.rename_key(timestamp_path, event_path!("date_happened"));
+        if !log.contains(event_path!("text")) {
+            if let Some(message_path) = log.message_path().cloned().as_ref() {
+                log.rename_key(message_path, event_path!("text"));
+            }
         }
+
+        if !log.contains(event_path!("text")) {
+            match self.missing_field_policy {
+                DatadogEventsMissingFieldPolicy::Drop => {
+                    emit!(ParserMissingFieldError::<DROP_EVENT> { field: "text" });
+                    return None;
+                }
+                DatadogEventsMissingFieldPolicy::Fill => {
+                    log.insert(event_path!("text"), self.default_text.clone());
+                }
+            }
+        }
+
+        if self.markdown {
+            if let Some(text) = log
+                .get(event_path!("text"))
+                .map(|text| text.to_string_lossy().into_owned())
+            {
+                if !text.starts_with("%%%") {
+                    log.insert(event_path!("text"), format!("%%% \n{}\n %%%", text));
+                }
+            }
+        }
+
+        if !log.contains(event_path!("host")) {
+            if let Some(host_path) = log.host_path().cloned().as_ref() {
+                log.rename_key(host_path, event_path!("host"));
+            }
+        }
+
+        if !log.contains(event_path!("host")) {
+            if let Some(default_host) = &self.default_host {
+                log.insert(event_path!("host"), default_host.clone());
+            }
+        }
+
+        if !log.contains(event_path!("date_happened")) {
+            let timestamp_path = match &self.timestamp_field {
+                Some(field) => Some(OwnedTargetPath::event(field.0.clone())),
+                None => log.timestamp_path().cloned(),
+            };
+            // The encoder sends timestamps as Unix seconds
+            if let Some(timestamp_path) = timestamp_path.as_ref() {
+                log.rename_key(timestamp_path, event_path!("date_happened"));
+            }
+        }
+
+        if !log.contains(event_path!("source_type_name")) {
+            if let Some(source_type_path) = log.source_type_path().cloned().as_ref() {
+                log.rename_key(source_type_path, event_path!("source_type_name"));
+            }
+        }
+
+        if !log.contains(event_path!("source_type_name")) {
+            if let Some(default_source_type_name) = &self.default_source_type_name {
+                log.insert(
+                    event_path!("source_type_name"),
+                    default_source_type_name.clone(),
+                );
+            }
+        }
+
+        if let Some(aggregation_key) = log
+            .get(event_path!("aggregation_key"))
+            .map(|aggregation_key| aggregation_key.to_string_lossy().into_owned())
+        {
+            if aggregation_key.chars().count() > self.max_aggregation_key_length {
+                debug!(
+                    message = "Truncating aggregation key.",
+                    %aggregation_key,
+                    max_length = self.max_aggregation_key_length,
+                    internal_log_rate_limit = true
+                );
+                let truncated: String = aggregation_key
+                    .chars()
+                    .take(self.max_aggregation_key_length)
+                    .collect();
+                log.insert(event_path!("aggregation_key"), truncated);
+            }
+        }
+
+        merge_metadata_tags(&mut log);
+
+        if let Some(alert_type) = log
+            .get(event_path!("alert_type"))
+            .map(|alert_type| alert_type.to_string_lossy().into_owned())
+        {
+            match normalize_alert_type(&alert_type) {
+                Some(normalized) => {
+                    log.insert(event_path!("alert_type"), normalized);
+                }
+                None => {
+                    // Datadog falls back to `info` for events without an `alert_type`.
+                    debug!(
+                        message = "Removing unsupported alert type.",
+                        %alert_type,
+                        internal_log_rate_limit = true
+                    );
+                    log.remove(event_path!("alert_type"));
+                }
+            }
+        }
+
+        if let Some(priority) = log
+            .get(event_path!("priority"))
+            .map(|priority| priority.to_string_lossy().into_owned())
+        {
+            if !matches!(priority.as_str(), "normal" | "low") {
+                debug!(
+                    message = "Replacing unsupported priority.",
+                    %priority,
+                    default_priority = self.default_priority.as_str(),
+                    internal_log_rate_limit = true
+                );
+                log.insert(event_path!("priority"), self.default_priority.as_str());
+            }
+        }
+
+        if self.unknown_field_policy != DatadogEventsUnknownFieldPolicy::Strip {
+            let unknown_fields = disallowed_fields(&log, &self.allowed_fields);
+            if !unknown_fields.is_empty() {
+                let fields = unknown_fields.join(", ");
+                if self.unknown_field_policy == DatadogEventsUnknownFieldPolicy::Reject {
+                    warn!(
+                        message = "Rejecting event with fields that aren't sent to Datadog.",
+                        %fields,
+                        internal_log_rate_limit = true
+                    );
+                    emit!(ComponentEventsDropped::<INTENTIONAL> {
+                        count: 1,
+                        reason: "Event has fields that aren't sent to Datadog.",
+                    });
+                    return None;
+                }
+                warn!(
+                    message = "Removing fields that aren't sent to Datadog.",
+                    %fields,
+                    internal_log_rate_limit = true
+                );
+            }
+        }
+
+        Some(Event::from(log))
     }
+}
 
-    if !log.contains(event_path!("source_type_name")) {
-        if let Some(source_type_path) = log.source_type_path().cloned().as_ref() {
-            log.rename_key(source_type_path, event_path!("source_type_name"));
+/// Adds the tags held by the metadata of the event to its `tags`, without duplicates.
+///
+/// These are the `tags` set in the metadata, such as with `%tags` in VRL, and the `ddtags` received
+/// by the `datadog_agent` source.
+fn merge_metadata_tags(log: &mut LogEvent) {
+    let metadata_tags: Vec<String> = [
+        metadata_path!("tags"),
+        metadata_path!("datadog_agent", "ddtags"),
+    ]
+    .into_iter()
+    .filter_map(|path| log.get(path))
+    .flat_map(tag_values)
+    .collect();
+    if metadata_tags.is_empty() {
+        return;
+    }
+
+    let mut tags = log
+        .get(event_path!("tags"))
+        .map(tag_values)
+        .unwrap_or_default();
+    for tag in metadata_tags {
+        if !tags.contains(&tag) {
+            tags.push(tag);
         }
     }
+    log.insert(
+        event_path!("tags"),
+        tags.into_iter().map(Value::from).collect::<Vec<_>>(),
+    );
+}
 
-    Some(Event::from(log))
+/// Returns the tags held by an array, or by a comma-separated string.
+fn tag_values(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect(),
+        Value::Bytes(bytes) => String::from_utf8_lossy(bytes)
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Maps an `alert_type` to the one the Datadog Events API accepts for it, if any.
+pub(super) fn normalize_alert_type(alert_type: &str) -> Option<&'static str> {
+    match alert_type.to_ascii_lowercase().as_str() {
+        "error" | "err" => Some("error"),
+        "warning" | "warn" => Some("warning"),
+        "info" | "information" => Some("info"),
+        "success" | "ok" => Some("success"),
+        _ => None,
+    }
 }
 
 #[async_trait]
@@ -1,6 +1,8 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, io::Read, num::NonZeroUsize, sync::Arc};
 
 use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use flate2::read::GzDecoder;
 use futures::{
     channel::mpsc::{Receiver, TryRecvError},
     stream::Stream,
@@ -9,13 +11,32 @@ use futures::{
 use hyper::StatusCode;
 use indoc::indoc;
 use similar_asserts::assert_eq;
+use vector_common::request_metadata::MetaDescriptive;
 use vector_core::event::{BatchNotifier, BatchStatus};
 
 use super::*;
 use crate::{
     config::SinkConfig,
-    event::EventArray,
-    sinks::util::test::{build_test_server_status, load_sink},
+    event::{
+        Event, EventArray, EventStatus, Finalizable, LogEvent, Metric, MetricKind, MetricValue,
+    },
+    sinks::{
+        datadog::events::{
+            config::{
+                DatadogEventsApiVersion, DatadogEventsMissingFieldPolicy, DatadogEventsSerializer,
+                DatadogEventsUnknownFieldPolicy,
+            },
+            request_builder::{
+                allowed_fields, disallowed_fields, DatadogEventsRequest,
+                DatadogEventsRequestBuilder, FieldError, RequestBuildError,
+            },
+            sink::{normalize_alert_type, split_batch, EventFields},
+        },
+        util::{
+            test::{build_test_server_status, load_sink},
+            Compression, ElementCount, RequestBuilder,
+        },
+    },
     test_util::{
         components::{self, COMPONENT_ERROR_TAGS, HTTP_SINK_TAGS},
         next_addr, random_lines_with_stream,
@@ -40,6 +61,25 @@ fn random_events_with_stream(
     )
 }
 
+fn build_request(
+    builder: &DatadogEventsRequestBuilder,
+    events: Vec<Event>,
+) -> DatadogEventsRequest {
+    let (metadata, request_metadata_builder, events) = builder.split_input((None, events));
+    let payload = builder
+        .encode_events(events)
+        .expect("encoding events should not fail");
+    let request_metadata = request_metadata_builder.build(&payload);
+    builder.build_request(metadata, request_metadata, payload)
+}
+
+fn titled_event(message: &str) -> Event {
+    let mut log = LogEvent::default();
+    log.insert("title", "All!");
+    log.insert("text", message);
+    Event::from(log)
+}
+
 async fn start_test(
     http_status: StatusCode,
     batch_status: BatchStatus,
@@ -47,6 +87,14 @@ async fn start_test(
     let config = indoc! {r#"
             default_api_key = "atoken"
         "#};
+    start_test_with_config(config, http_status, batch_status).await
+}
+
+async fn start_test_with_config(
+    config: &str,
+    http_status: StatusCode,
+    batch_status: BatchStatus,
+) -> (Vec<String>, Receiver<(http::request::Parts, Bytes)>) {
     let (mut config, cx) = load_sink::<DatadogEventsConfig>(config).unwrap();
 
     let addr = next_addr();
@@ -81,6 +129,7 @@ async fn smoke() {
     let output = rx.take(expected.len()).collect::<Vec<_>>().await;
 
     for (i, val) in output.iter().enumerate() {
+        assert_eq!(val.0.uri.path(), "/api/v1/events");
         assert_eq!(
             val.0.headers.get("Content-Type").unwrap(),
             "application/json"
@@ -100,6 +149,832 @@ async fn smoke() {
     }
 }
 
+#[tokio::test]
+async fn batched_v2() {
+    let config = indoc! {r#"
+            default_api_key = "atoken"
+            api_version = "v2"
+            batch.max_events = 4
+        "#};
+    let (expected, mut rx) =
+        start_test_with_config(config, StatusCode::OK, BatchStatus::Delivered).await;
+
+    let mut messages = Vec::new();
+    while messages.len() < expected.len() {
+        let (parts, body) = rx.next().await.expect("request should be received");
+        assert_eq!(parts.uri.path(), "/api/v2/events");
+        assert_eq!(
+            parts.headers.get("Content-Type").unwrap(),
+            "application/json"
+        );
+
+        let json: serde_json::Value = serde_json::from_slice(&body[..]).expect("decoding json");
+        let events = json.as_array().expect("body should be an array of events");
+        assert!(!events.is_empty() && events.len() <= 4);
+        messages.extend(
+            events
+                .iter()
+                .map(|event| event.get("text").unwrap().as_str().unwrap().to_owned()),
+        );
+    }
+
+    assert_eq!(messages, expected);
+}
+
+#[test]
+fn request_bodies() {
+    // A single event is sent as it is to the v1 endpoint
+    let builder = DatadogEventsRequestBuilder::default();
+    let request = build_request(&builder, vec![titled_event("one")]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(request.element_count(), 1);
+    assert_eq!(json, serde_json::json!({"text": "one", "title": "All!"}));
+
+    // Batches of events are sent as an array to the v2 endpoint
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        api_version: DatadogEventsApiVersion::V2,
+        ..Default::default()
+    });
+    let request = build_request(
+        &builder,
+        vec![
+            titled_event("one"),
+            titled_event("two"),
+            titled_event("three"),
+        ],
+    );
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(request.element_count(), 3);
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"text": "one", "title": "All!"},
+            {"text": "two", "title": "All!"},
+            {"text": "three", "title": "All!"},
+        ])
+    );
+}
+
+#[test]
+fn max_events_per_request() {
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        api_version: DatadogEventsApiVersion::V2,
+        ..Default::default()
+    });
+    let events = (0..250)
+        .map(|i| titled_event(&format!("event {}", i)))
+        .collect();
+    let api_key = Some(Arc::from("a-key"));
+
+    let batches = split_batch((api_key.clone(), events), NonZeroUsize::new(100));
+    let requests: Vec<_> = batches
+        .into_iter()
+        .map(|(batch_api_key, events)| {
+            assert_eq!(batch_api_key, api_key);
+            build_request(&builder, events)
+        })
+        .collect();
+
+    let counts: Vec<_> = requests.iter().map(ElementCount::element_count).collect();
+    assert_eq!(counts, vec![100, 100, 50]);
+
+    let json: serde_json::Value = serde_json::from_slice(&requests[2].body[..]).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 50);
+    assert_eq!(json[0]["text"], "event 200");
+
+    // Without a cap, the batch is kept whole
+    let events = (0..250).map(|_| titled_event("event")).collect();
+    let batches = split_batch((None, events), None);
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].1.len(), 250);
+}
+
+#[test]
+fn split_batch_finalizers() {
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        api_version: DatadogEventsApiVersion::V2,
+        ..Default::default()
+    });
+
+    // Each group of events belongs to a batch of its own, which is split the same way
+    let mut events = Vec::new();
+    let mut receivers = Vec::new();
+    for size in [100, 100, 50] {
+        let (batch, receiver) = BatchNotifier::new_with_receiver();
+        events.extend((0..size).map(|_| titled_event("event").with_batch_notifier(&batch)));
+        receivers.push(receiver);
+    }
+
+    let mut requests: Vec<_> = split_batch((None, events), NonZeroUsize::new(100))
+        .into_iter()
+        .map(|(_, events)| build_request(&builder, events))
+        .collect();
+
+    let counts: Vec<_> = requests
+        .iter()
+        .map(|request| request.metadata.finalizers.len())
+        .collect();
+    assert_eq!(counts, vec![100, 100, 50]);
+    assert_eq!(counts.iter().sum::<usize>(), 250);
+
+    // Finalizing a request only resolves the batch of its own events
+    let statuses = [
+        (EventStatus::Delivered, BatchStatus::Delivered),
+        (EventStatus::Rejected, BatchStatus::Rejected),
+        (EventStatus::Delivered, BatchStatus::Delivered),
+    ];
+    for (index, (request, (event_status, batch_status))) in
+        requests.iter_mut().zip(statuses).enumerate()
+    {
+        for receiver in &mut receivers[index..] {
+            assert!(receiver.try_recv().is_err());
+        }
+        request.take_finalizers().update_status(event_status);
+
+        assert_eq!(receivers[index].try_recv(), Ok(batch_status));
+    }
+}
+
+#[tokio::test]
+async fn gzip_compression() {
+    let config = indoc! {r#"
+            default_api_key = "atoken"
+            compression = "gzip"
+        "#};
+    let (expected, rx) =
+        start_test_with_config(config, StatusCode::OK, BatchStatus::Delivered).await;
+
+    let output = rx.take(expected.len()).collect::<Vec<_>>().await;
+
+    for (i, val) in output.iter().enumerate() {
+        assert_eq!(val.0.headers.get("Content-Encoding").unwrap(), "gzip");
+
+        let mut body = String::new();
+        GzDecoder::new(&val.1[..])
+            .read_to_string(&mut body)
+            .expect("decompressing body");
+        let json: serde_json::Value = serde_json::from_str(&body).expect("decoding json");
+
+        let message = json.get("text").unwrap().as_str().unwrap();
+        assert_eq!(message, expected[i]);
+    }
+}
+
+#[test]
+fn request_metadata_sizes() {
+    // Without compression, both sizes are the length of the JSON body
+    let builder = DatadogEventsRequestBuilder::default();
+    let request = build_request(&builder, vec![titled_event("one")]);
+    let metadata = request.get_metadata();
+
+    assert_eq!(metadata.request_encoded_size(), request.body.len());
+    assert_eq!(metadata.request_wire_size(), request.body.len());
+
+    // With compression, the wire size is the length of the compressed body
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        compression: Compression::gzip_default(),
+        ..Default::default()
+    });
+    let request = build_request(&builder, vec![titled_event(&"one".repeat(100))]);
+    let metadata = request.get_metadata();
+
+    let mut json = Vec::new();
+    GzDecoder::new(&request.body[..])
+        .read_to_end(&mut json)
+        .expect("decompressing body");
+    serde_json::from_slice::<serde_json::Value>(&json).expect("decoding json");
+
+    assert_eq!(metadata.request_encoded_size(), json.len());
+    assert_eq!(metadata.request_wire_size(), request.body.len());
+    assert!(metadata.request_wire_size() < metadata.request_encoded_size());
+}
+
+#[test]
+fn min_compression_bytes() {
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        compression: Compression::gzip_default(),
+        min_compression_bytes: Some(1024),
+        ..Default::default()
+    });
+
+    // A small event is sent uncompressed
+    let request = build_request(&builder, vec![titled_event("one")]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(request.compression.content_encoding(), None);
+    assert_eq!(json, serde_json::json!({"text": "one", "title": "All!"}));
+    assert_eq!(
+        request.get_metadata().request_wire_size(),
+        request.body.len()
+    );
+
+    // A large event is compressed
+    let text = "one".repeat(1000);
+    let request = build_request(&builder, vec![titled_event(&text)]);
+    let mut body = String::new();
+    GzDecoder::new(&request.body[..])
+        .read_to_string(&mut body)
+        .expect("decompressing body");
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(request.compression.content_encoding(), Some("gzip"));
+    assert_eq!(json, serde_json::json!({"text": text, "title": "All!"}));
+}
+
+#[test]
+fn additional_fields() {
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        additional_fields: vec!["custom_attribute".into()],
+        ..Default::default()
+    });
+
+    let mut log = LogEvent::default();
+    log.insert("title", "All!");
+    log.insert("text", "one");
+    log.insert("custom_attribute", "kept");
+    log.insert("invalid", "Tik");
+    let request = build_request(&builder, vec![Event::from(log)]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({"custom_attribute": "kept", "text": "one", "title": "All!"})
+    );
+}
+
+#[test]
+fn rename_fields() {
+    let config = DatadogEventsConfig {
+        rename_fields: BTreeMap::from([
+            ("description".to_owned(), "text".to_owned()),
+            ("subject".to_owned(), "title".to_owned()),
+        ]),
+        ..Default::default()
+    };
+    let fields = EventFields::new(&config).unwrap();
+    let builder = DatadogEventsRequestBuilder::new(&config);
+
+    let mut log = LogEvent::default();
+    log.insert("subject", "Deployment");
+    log.insert("description", "Version 1.2.3 is out");
+    let event = fields
+        .ensure_required_fields(Event::from(log))
+        .expect("event should have a title and a text once renamed");
+    let request = build_request(&builder, vec![event]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({"text": "Version 1.2.3 is out", "title": "Deployment"})
+    );
+
+    // Fields that are already there are kept
+    let mut log = titled_event("one").into_log();
+    log.insert("subject", "Deployment");
+    let event = fields.ensure_required_fields(Event::from(log)).unwrap();
+
+    assert_eq!(event.as_log()["title"], "All!".into());
+}
+
+#[test]
+fn rename_nested_fields() {
+    let fields = EventFields::new(&DatadogEventsConfig {
+        rename_fields: BTreeMap::from([
+            ("meta.title".to_owned(), "title".to_owned()),
+            ("message".to_owned(), "details.text".to_owned()),
+        ]),
+        missing_field_policy: DatadogEventsMissingFieldPolicy::Fill,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let mut log = LogEvent::default();
+    log.insert("meta.title", "Deployment");
+    log.insert("meta.team", "core");
+    log.insert("message", "Version 1.2.3 is out");
+    let log = fields
+        .ensure_required_fields(Event::from(log))
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["title"], "Deployment".into());
+    assert!(!log.contains("meta.title"));
+    assert_eq!(log["meta.team"], "core".into());
+    assert_eq!(log["details.text"], "Version 1.2.3 is out".into());
+
+    // Fields that aren't valid paths are rejected
+    for (field, new_field) in [("meta.title[", "title"), ("subject", "title[")] {
+        let config = DatadogEventsConfig {
+            rename_fields: BTreeMap::from([(field.to_owned(), new_field.to_owned())]),
+            ..Default::default()
+        };
+        assert!(
+            EventFields::new(&config).is_err(),
+            "{} = {}",
+            field,
+            new_field
+        );
+    }
+}
+
+#[test]
+fn msgpack_serializer() {
+    let event = || {
+        let mut log = LogEvent::default();
+        log.insert("title", "All!");
+        log.insert("text", "one");
+        log.insert("date_happened", 1_600_000_000);
+        log.insert("tags", vec!["env:prod", "team:core"]);
+        Event::from(log)
+    };
+
+    for api_version in [DatadogEventsApiVersion::V1, DatadogEventsApiVersion::V2] {
+        let json_request = build_request(
+            &DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+                api_version,
+                ..Default::default()
+            }),
+            vec![event()],
+        );
+        let msgpack_request = build_request(
+            &DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+                api_version,
+                serializer: DatadogEventsSerializer::Msgpack,
+                ..Default::default()
+            }),
+            vec![event()],
+        );
+
+        assert_eq!(json_request.content_type, "application/json");
+        assert_eq!(msgpack_request.content_type, "application/msgpack");
+
+        let json: serde_json::Value = serde_json::from_slice(&json_request.body[..]).unwrap();
+        let msgpack: serde_json::Value = rmp_serde::from_slice(&msgpack_request.body[..]).unwrap();
+        assert_eq!(json, msgpack);
+    }
+}
+
+#[test]
+fn api_key_field() {
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+    let builder = DatadogEventsRequestBuilder::default();
+
+    let mut log = titled_event("one").into_log();
+    log.metadata_mut()
+        .set_datadog_api_key(Arc::from("from_metadata"));
+    log.insert("_dd_api_key", "from_field");
+    let event = fields.ensure_required_fields(log.into()).unwrap();
+
+    let api_key = event.metadata().datadog_api_key();
+    let (metadata, _, events) = builder.split_input((api_key, vec![event]));
+
+    assert_eq!(metadata.api_key.as_deref(), Some("from_field"));
+    assert!(!events[0].as_log().contains("_dd_api_key"));
+
+    // The metadata key is used when the field is missing
+    let mut log = titled_event("one").into_log();
+    log.metadata_mut()
+        .set_datadog_api_key(Arc::from("from_metadata"));
+    let event = fields.ensure_required_fields(log.into()).unwrap();
+
+    assert_eq!(
+        event.metadata().datadog_api_key().as_deref(),
+        Some("from_metadata")
+    );
+}
+
+#[test]
+fn missing_text_dropped() {
+    let mut log = LogEvent::default();
+    log.insert("title", "All!");
+
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+
+    assert!(fields.ensure_required_fields(Event::from(log)).is_none());
+}
+
+#[test]
+fn missing_text_filled() {
+    let fields = EventFields::new(&DatadogEventsConfig {
+        missing_field_policy: DatadogEventsMissingFieldPolicy::Fill,
+        default_text: "(no text)".to_owned(),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let mut log = LogEvent::default();
+    log.insert("title", "All!");
+    let log = fields
+        .ensure_required_fields(Event::from(log))
+        .expect("event should be kept")
+        .into_log();
+
+    assert_eq!(log["title"], "All!".into());
+    assert_eq!(log["text"], "(no text)".into());
+
+    // The message is still preferred over the default text
+    let mut log = LogEvent::from("a message");
+    log.insert("title", "All!");
+    let log = fields
+        .ensure_required_fields(Event::from(log))
+        .expect("event should be kept")
+        .into_log();
+
+    assert_eq!(log["text"], "a message".into());
+}
+
+#[test]
+fn alert_types() {
+    for (alert_type, expected) in [
+        ("error", Some("error")),
+        ("ERROR", Some("error")),
+        ("err", Some("error")),
+        ("warning", Some("warning")),
+        ("warn", Some("warning")),
+        ("info", Some("info")),
+        ("information", Some("info")),
+        ("success", Some("success")),
+        ("ok", Some("success")),
+        ("critical", None),
+        ("", None),
+    ] {
+        assert_eq!(normalize_alert_type(alert_type), expected, "{}", alert_type);
+    }
+
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+    let mut log = titled_event("one").into_log();
+    log.insert("alert_type", "warn");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["alert_type"], "warning".into());
+
+    let mut log = titled_event("one").into_log();
+    log.insert("alert_type", "critical");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert!(!log.contains("alert_type"));
+}
+
+#[test]
+fn unsupported_priority() {
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+
+    let mut log = titled_event("one").into_log();
+    log.insert("priority", "high");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["priority"], "normal".into());
+
+    let mut log = titled_event("one").into_log();
+    log.insert("priority", "low");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["priority"], "low".into());
+}
+
+#[test]
+fn validate_events() {
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig::default());
+
+    assert_eq!(builder.validate(&titled_event("one")), Ok(()));
+
+    let mut log = LogEvent::default();
+    log.insert("title", "All!");
+    log.insert("invalid", "Tik");
+    log.insert("alert_type", "fatal");
+    log.insert("priority", "urgent");
+    assert_eq!(
+        builder.validate(&log.into()),
+        Err(vec![
+            FieldError::Missing { field: "text" },
+            FieldError::NotAllowed {
+                field: "invalid".to_string()
+            },
+            FieldError::UnsupportedValue {
+                field: "alert_type",
+                value: "fatal".to_string()
+            },
+            FieldError::UnsupportedValue {
+                field: "priority",
+                value: "urgent".to_string()
+            },
+        ])
+    );
+
+    let metric = Event::Metric(Metric::new(
+        "counter",
+        MetricKind::Absolute,
+        MetricValue::Counter { value: 1.0 },
+    ));
+    assert_eq!(builder.validate(&metric), Err(vec![FieldError::NotALog]));
+}
+
+#[test]
+fn unknown_field_policy() {
+    let allowed = allowed_fields(&["team.name".into()]);
+    let mut log = titled_event("one").into_log();
+    log.insert("team.name", "core");
+    log.insert("invalid", "Tik");
+    log.insert("user.id", 123);
+
+    assert_eq!(
+        disallowed_fields(&log, &allowed),
+        vec!["invalid".to_string(), "user.id".to_string()]
+    );
+
+    // Events with unknown fields are rejected
+    let fields = EventFields::new(&DatadogEventsConfig {
+        additional_fields: vec!["team.name".into()],
+        unknown_field_policy: DatadogEventsUnknownFieldPolicy::Reject,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(fields.ensure_required_fields(log.clone().into()).is_none());
+    assert!(fields.ensure_required_fields(titled_event("one")).is_some());
+
+    // Or kept, without the unknown fields
+    let config = DatadogEventsConfig {
+        unknown_field_policy: DatadogEventsUnknownFieldPolicy::Warn,
+        ..Default::default()
+    };
+    let event = EventFields::new(&config)
+        .unwrap()
+        .ensure_required_fields(log.into())
+        .unwrap();
+    let request = build_request(&DatadogEventsRequestBuilder::new(&config), vec![event]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(json, serde_json::json!({"text": "one", "title": "All!"}));
+}
+
+#[test]
+fn metadata_tags() {
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+
+    let mut log = titled_event("one").into_log();
+    log.insert("tags", vec!["env:prod", "team:a"]);
+    log.insert("%tags", vec!["team:a", "service:web"]);
+    log.insert("%datadog_agent.ddtags", "env:prod,version:1.2");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(
+        log["tags"],
+        vec!["env:prod", "team:a", "service:web", "version:1.2"].into()
+    );
+
+    // Events without metadata tags are left as they are
+    let mut log = titled_event("one").into_log();
+    log.insert("tags", "env:prod");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["tags"], "env:prod".into());
+}
+
+#[test]
+fn date_happened() {
+    let config = DatadogEventsConfig {
+        timestamp_field: Some("time".into()),
+        ..Default::default()
+    };
+    let fields = EventFields::new(&config).unwrap();
+    let builder = DatadogEventsRequestBuilder::new(&config);
+    let timestamp = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+
+    // An existing `date_happened` is kept
+    let mut log = titled_event("one").into_log();
+    log.insert("date_happened", 1_600_000_000);
+    log.insert("time", timestamp);
+    let event = fields.ensure_required_fields(log.into()).unwrap();
+    let request = build_request(&builder, vec![event]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(json["date_happened"], 1_600_000_000);
+
+    // Otherwise it's derived from the timestamp field
+    let mut log = titled_event("one").into_log();
+    log.insert("time", timestamp);
+    let event = fields.ensure_required_fields(log.into()).unwrap();
+    let request = build_request(&builder, vec![event]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(json["date_happened"], 1_672_531_200);
+}
+
+#[test]
+fn date_happened_from_event_time() {
+    let config = DatadogEventsConfig {
+        timestamp_field: Some("event_time".into()),
+        ..Default::default()
+    };
+    let fields = EventFields::new(&config).unwrap();
+    let builder = DatadogEventsRequestBuilder::new(&config);
+
+    let mut log = titled_event("one").into_log();
+    log.insert(
+        "timestamp",
+        Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap(),
+    );
+    log.insert(
+        "event_time",
+        Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+    );
+    let event = fields.ensure_required_fields(log.into()).unwrap();
+    let request = build_request(&builder, vec![event]);
+    let json: serde_json::Value = serde_json::from_slice(&request.body[..]).unwrap();
+
+    assert_eq!(json["date_happened"], 1_672_531_200);
+    assert!(json.get("event_time").is_none());
+}
+
+#[test]
+fn default_host() {
+    let fields = EventFields::new(&DatadogEventsConfig {
+        default_host: Some("my-host".to_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let log = titled_event("one").into_log();
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["host"], "my-host".into());
+
+    let mut log = titled_event("one").into_log();
+    log.insert("host", "other-host");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["host"], "other-host".into());
+
+    // The hostname of the machine can be used instead
+    let fields = EventFields::new(&DatadogEventsConfig {
+        default_to_hostname: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let log = titled_event("one").into_log();
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["host"], crate::get_hostname().unwrap().into());
+}
+
+#[test]
+fn default_source_type_name() {
+    let fields = EventFields::new(&DatadogEventsConfig {
+        default_source_type_name: Some("vector".to_owned()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let log = titled_event("one").into_log();
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["source_type_name"], "vector".into());
+
+    let mut log = titled_event("one").into_log();
+    log.insert("source_type_name", "nagios");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["source_type_name"], "nagios".into());
+}
+
+#[test]
+fn markdown_text() {
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+    let log = titled_event("# Deployed\n* web").into_log();
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["text"], "# Deployed\n* web".into());
+
+    let fields = EventFields::new(&DatadogEventsConfig {
+        markdown: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let log = titled_event("# Deployed\n* web").into_log();
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["text"], "%%% \n# Deployed\n* web\n %%%".into());
+}
+
+#[test]
+fn long_aggregation_key() {
+    let fields = EventFields::new(&DatadogEventsConfig::default()).unwrap();
+
+    let mut log = titled_event("one").into_log();
+    log.insert("aggregation_key", "a".repeat(150));
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["aggregation_key"], "a".repeat(100).into());
+
+    let mut log = titled_event("one").into_log();
+    log.insert("aggregation_key", "deploy");
+    let log = fields
+        .ensure_required_fields(log.into())
+        .unwrap()
+        .into_log();
+
+    assert_eq!(log["aggregation_key"], "deploy".into());
+}
+
+#[tokio::test]
+async fn oversized_event_dropped() {
+    crate::metrics::init_test();
+    let counter = |name: &'static str| {
+        crate::metrics::Controller::get()
+            .expect("There must be a controller")
+            .capture_metrics()
+            .into_iter()
+            .filter(|metric| metric.name() == name)
+            .map(|metric| match metric.value() {
+                MetricValue::Counter { value } => *value,
+                _ => 0.0,
+            })
+            .sum::<f64>()
+    };
+    let discarded_before = counter("component_discarded_events_total");
+    let errors_before = counter("component_errors_total");
+
+    let (mut config, cx) = load_sink::<DatadogEventsConfig>(indoc! {r#"
+            default_api_key = "atoken"
+            max_payload_bytes = 100
+        "#})
+    .unwrap();
+    // Nothing is sent, so there is no server behind the endpoint
+    config.dd_common.endpoint = Some(format!("http://{}", next_addr()));
+    let (sink, _) = config.build(cx).await.unwrap();
+    sink.run_events(vec![titled_event(&"a".repeat(100))])
+        .await
+        .unwrap();
+
+    // The event is only reported once, as dropped
+    assert_eq!(
+        counter("component_discarded_events_total"),
+        discarded_before + 1.0
+    );
+    assert_eq!(counter("component_errors_total"), errors_before);
+
+    let builder = DatadogEventsRequestBuilder::new(&DatadogEventsConfig {
+        max_payload_bytes: Some(100),
+        ..Default::default()
+    });
+
+    let (_, _, events) = builder.split_input((None, vec![titled_event(&"a".repeat(100))]));
+    let result = builder.encode_events(events);
+
+    assert!(matches!(
+        result,
+        Err(RequestBuildError::PayloadTooBig { max_size: 100, .. })
+    ));
+
+    // Smaller events are kept
+    let request = build_request(&builder, vec![titled_event("one")]);
+    assert_eq!(request.element_count(), 1);
+}
+
 #[tokio::test]
 async fn handles_failure() {
     let (_expected, mut rx) = start_test(StatusCode::FORBIDDEN, BatchStatus::Rejected).await;
@@ -1,15 +1,22 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
 
-use http::Uri;
+use http::{StatusCode, Uri};
+use lookup::{lookup_v2::ConfigValuePath, owned_value_path};
 use tower::ServiceBuilder;
 use vector_common::sensitive_string::SensitiveString;
 
 use super::{
     healthcheck, NewRelicApiResponse, NewRelicApiService, NewRelicEncoder, NewRelicSink,
-    NewRelicSinkError,
+    NewRelicSinkError, LOGS_KEY, METRICS_KEY, TRUNCATION_MARKER,
 };
 
-use crate::{http::HttpClient, sinks::prelude::*};
+use crate::{
+    http::HttpClient,
+    sinks::{
+        prelude::*,
+        util::{statistic::validate_quantiles, UriSerde},
+    },
+};
 
 /// New Relic region.
 #[configurable_component]
@@ -42,6 +49,517 @@ pub enum NewRelicApi {
     Logs,
 }
 
+/// How to handle NaN and infinite values, which New Relic doesn't support.
+#[configurable_component]
+#[derive(Clone, Copy, Derivative, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum NewRelicNanPolicy {
+    /// Drop the metric, event, or log holding the value.
+    #[derivative(Default)]
+    Drop,
+
+    /// Replace the value with zero.
+    Zero,
+
+    /// Fail the whole batch holding the value.
+    ///
+    /// None of the metrics, events, or logs of the batch are sent, including those without NaN or
+    /// infinite values. They are all rejected and reported as dropped, and the failure is reported
+    /// as an error of the sink.
+    Error,
+}
+
+/// How to handle incremental metrics without an interval, which New Relic requires for counts and
+/// summaries.
+#[configurable_component]
+#[derive(Clone, Copy, Derivative, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum NewRelicMissingIntervalPolicy {
+    /// Drop the metric.
+    #[derivative(Default)]
+    Drop,
+
+    /// Send counters as gauges, which don't need an interval. Other metrics are dropped.
+    AsGauge,
+
+    /// Send the metric with an interval of `synthetic_interval_secs`.
+    SynthesizeInterval,
+}
+
+/// How to handle the sample rate of counters, taken from the `sample_rate` of their metadata or
+/// tags.
+///
+/// A counter sampled at a rate of `0.1` only counted one in ten occurrences.
+#[configurable_component]
+#[derive(Clone, Copy, Derivative, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum NewRelicSampleRatePolicy {
+    /// Send the value as it is.
+    #[derivative(Default)]
+    Ignore,
+
+    /// Divide the value by the sample rate.
+    Scale,
+
+    /// Send the value as it is, with the sample rate as the `sampleRate` attribute.
+    Attribute,
+}
+
+/// How to encode the values of attributes that aren't valid UTF-8, as New Relic only accepts text.
+#[configurable_component]
+#[derive(Clone, Copy, Derivative, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum NewRelicBinaryEncoding {
+    /// Replace the invalid sequences with the `U+FFFD` replacement character.
+    #[derivative(Default)]
+    LossyUtf8,
+
+    /// Encode the whole value as standard Base64.
+    Base64,
+}
+
+/// Which value is kept when a field of an event and a field of the JSON object in its `message`
+/// have the same key.
+#[configurable_component]
+#[derive(Clone, Copy, Derivative, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum NewRelicFieldPrecedence {
+    /// Keep the field of the JSON object.
+    #[derivative(Default)]
+    JsonWins,
+
+    /// Keep the field of the event.
+    FieldsWin,
+}
+
+/// Configuration of the attributes set on the data of every New Relic API.
+#[configurable_component]
+#[derive(Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NewRelicAttributesConfig {
+    /// The `host` attribute set on data that doesn't have one.
+    #[configurable(metadata(docs::examples = "${HOSTNAME}"))]
+    #[configurable(metadata(docs::examples = "my-host"))]
+    pub default_host: Option<String>,
+
+    /// Attributes set on all data, unless it already has an attribute with the same key, such as a
+    /// tag of a metric.
+    #[serde(default)]
+    #[configurable(metadata(docs::additional_props_description = "An attribute and its value."))]
+    #[configurable(metadata(docs::examples = "default_attributes_examples()"))]
+    pub default_attributes: BTreeMap<String, String>,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub binary_encoding: NewRelicBinaryEncoding,
+
+    /// The maximum length of the text values of attributes, in bytes.
+    ///
+    /// Longer values, such as long stack traces, are truncated and end with `...` to show that
+    /// they were cut. New Relic truncates values over 4096 bytes itself, without any marker.
+    ///
+    /// This must be at least 3 bytes, to fit the marker.
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    #[configurable(metadata(docs::examples = 4096))]
+    pub max_value_bytes: Option<usize>,
+
+    /// Transformations applied, in order, to the attributes of all data.
+    ///
+    /// The fields New Relic reserves, such as the `eventType` of events and the `message` of logs,
+    /// aren't transformed, nor are the `unit`, `sampleRate`, and `vector.metric_kind` attributes
+    /// the sink sets on metrics.
+    #[serde(default)]
+    pub attribute_transformers: Vec<NewRelicAttributeTransformer>,
+}
+
+/// A transformation of attributes.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[configurable(metadata(docs::enum_tag_description = "The transformation applied to attributes."))]
+pub enum NewRelicAttributeTransformer {
+    /// Lowercase the keys of attributes.
+    LowercaseKeys,
+
+    /// Trim the whitespace around the values of attributes.
+    TrimValues,
+
+    /// Replace the values of an attribute, such as `err` with `error`.
+    RenameMap(NewRelicRenameMapConfig),
+}
+
+/// Replacements of the values of an attribute.
+#[configurable_component]
+#[derive(Clone, Debug)]
+pub struct NewRelicRenameMapConfig {
+    /// The key of the attribute whose values are replaced.
+    #[configurable(metadata(docs::examples = "level"))]
+    pub key: String,
+
+    /// The values of the attribute, and the values they're replaced with.
+    #[configurable(metadata(docs::additional_props_description = "A value and its replacement."))]
+    #[configurable(metadata(docs::examples = "rename_map_values_examples()"))]
+    pub values: BTreeMap<String, String>,
+}
+
+fn rename_map_values_examples() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("err".to_owned(), "error".to_owned()),
+        ("warn".to_owned(), "warning".to_owned()),
+    ])
+}
+
+fn default_attributes_examples() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("env".to_owned(), "production".to_owned()),
+        ("team".to_owned(), "observability".to_owned()),
+    ])
+}
+
+/// New Relic Metrics API configuration.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NewRelicMetricsConfig {
+    /// Whether to prefix metric names with their namespace, as `<namespace>.<name>`.
+    ///
+    /// New Relic has no concept of metric namespaces, so without this metrics from different
+    /// namespaces that share a name are indistinguishable.
+    #[serde(default)]
+    pub prefix_namespace: bool,
+
+    /// Whether to replace the characters of metric names that New Relic doesn't accept with `_`,
+    /// and truncate names to the 255 characters it accepts.
+    ///
+    /// Only ASCII letters and digits, `.`, `_`, `:`, and `-` are kept.
+    #[serde(default)]
+    pub sanitize_names: bool,
+
+    /// Whether to add the custom metadata of metrics to their attributes.
+    ///
+    /// As New Relic only supports scalar attribute values, nested objects and arrays are flattened
+    /// into separate attributes.
+    #[serde(default)]
+    pub metadata_attributes: bool,
+
+    /// The separator used to join the keys of flattened attributes.
+    #[serde(default = "default_flatten_separator")]
+    #[configurable(metadata(docs::examples = "_"))]
+    pub flatten_separator: String,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub nan_policy: NewRelicNanPolicy,
+
+    /// Whether to prefix attribute keys that clash with the fields of metrics, such as `timestamp`,
+    /// with `tag.`.
+    #[serde(default)]
+    pub sanitize_attribute_keys: bool,
+
+    /// Whether to send absolute counters as counts of their change since the previous reading of
+    /// their series, instead of as gauges.
+    ///
+    /// The first reading of a series is only used as the reference for the next one, and isn't
+    /// sent.
+    #[serde(default)]
+    pub convert_absolute_counters: bool,
+
+    /// How long the previous reading of a series is kept for `convert_absolute_counters`, in
+    /// seconds.
+    ///
+    /// Series without a reading for longer than this are forgotten, so that series that stop
+    /// being reported don't hold memory forever. Their next reading is only used as the reference
+    /// for the one after it again.
+    #[serde(default = "default_absolute_counters_ttl_secs")]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub absolute_counters_ttl_secs: u64,
+
+    /// The `unit` attribute set on metrics whose metadata doesn't have a `unit`.
+    #[configurable(metadata(docs::examples = "bytes"))]
+    pub default_unit: Option<String>,
+
+    /// The maximum number of attributes sent with a metric.
+    ///
+    /// Metrics with more attributes only keep the first ones, in the order of their keys.
+    #[configurable(metadata(docs::examples = 100))]
+    pub max_attributes: Option<usize>,
+
+    /// The quantiles of distributions sent as separate gauges alongside their summary, named
+    /// `<name>.p<percentile>`, such as `<name>.p95` for the `0.95` quantile.
+    ///
+    /// Quantiles must be between 0.0 and 1.0, inclusive.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = 0.5, docs::examples = 0.95, docs::examples = 0.99))]
+    pub distribution_quantiles: Vec<f64>,
+
+    /// A factor the values of gauges and counters are multiplied by, such as `1000` to send
+    /// seconds as milliseconds.
+    #[configurable(metadata(docs::examples = 1000.0))]
+    pub value_multiplier: Option<f64>,
+
+    /// Whether to add the kind of metrics, either `incremental` or `absolute`, to their attributes
+    /// as `vector.metric_kind`.
+    ///
+    /// The kind is otherwise lost, as absolute counters are sent as gauges.
+    #[serde(default)]
+    pub metric_kind_attribute: bool,
+
+    /// Whether to send gauges without a `type`, which New Relic then takes for gauges.
+    #[serde(default)]
+    pub omit_gauge_type: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub missing_interval_policy: NewRelicMissingIntervalPolicy,
+
+    /// The interval sent with incremental metrics that don't have one, when
+    /// `missing_interval_policy` is `synthesize_interval`.
+    #[serde(default = "default_synthetic_interval_secs")]
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    pub synthetic_interval_secs: u64,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub sample_rate_policy: NewRelicSampleRatePolicy,
+
+    /// How far in the future the timestamps of metrics can be, as New Relic rejects metrics
+    /// timestamped too far ahead.
+    ///
+    /// Later timestamps are replaced with the current time.
+    #[configurable(metadata(docs::type_unit = "seconds"))]
+    #[configurable(metadata(docs::examples = 600))]
+    pub max_future_secs: Option<u64>,
+
+    /// The key the metrics of requests are wrapped under.
+    ///
+    /// New Relic expects `metrics`, this is only meant for proxies and mock servers that expect
+    /// another one.
+    #[serde(default = "default_metrics_wrapper_key")]
+    #[configurable(metadata(docs::examples = "data"))]
+    pub wrapper_key: String,
+}
+
+fn default_flatten_separator() -> String {
+    ".".to_owned()
+}
+
+const fn default_synthetic_interval_secs() -> u64 {
+    60
+}
+
+fn default_metrics_wrapper_key() -> String {
+    METRICS_KEY.to_owned()
+}
+
+impl Default for NewRelicMetricsConfig {
+    fn default() -> Self {
+        Self {
+            prefix_namespace: false,
+            sanitize_names: false,
+            metadata_attributes: false,
+            flatten_separator: default_flatten_separator(),
+            nan_policy: NewRelicNanPolicy::default(),
+            sanitize_attribute_keys: false,
+            convert_absolute_counters: false,
+            absolute_counters_ttl_secs: default_absolute_counters_ttl_secs(),
+            default_unit: None,
+            max_attributes: None,
+            distribution_quantiles: Vec::new(),
+            value_multiplier: None,
+            metric_kind_attribute: false,
+            omit_gauge_type: false,
+            missing_interval_policy: NewRelicMissingIntervalPolicy::default(),
+            synthetic_interval_secs: default_synthetic_interval_secs(),
+            sample_rate_policy: NewRelicSampleRatePolicy::default(),
+            max_future_secs: None,
+            wrapper_key: default_metrics_wrapper_key(),
+        }
+    }
+}
+
+/// New Relic Events API configuration.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NewRelicEventsConfig {
+    /// The `eventType` set on events that don't already have one.
+    #[serde(default = "default_event_type")]
+    #[configurable(metadata(docs::examples = "MyPipeline"))]
+    pub default_event_type: String,
+
+    /// The field whose value is copied into the `eventType` of events, which keep the field too.
+    ///
+    /// Events without it keep their `eventType` field, or get `default_event_type`.
+    #[configurable(metadata(docs::examples = "kind"))]
+    pub event_type_field: Option<ConfigValuePath>,
+
+    /// Whether to drop events that don't have an `eventType`, instead of setting
+    /// `default_event_type`.
+    #[serde(default)]
+    pub require_event_type: bool,
+
+    /// Whether to keep the `message` of events holding a JSON object, which is otherwise removed
+    /// once the fields of the object are added to the event.
+    #[serde(default)]
+    pub keep_raw_message: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub field_precedence: NewRelicFieldPrecedence,
+
+    /// The field holding the timestamp of events, which is sent in milliseconds since the Unix
+    /// epoch.
+    ///
+    /// Integer timestamps are taken as seconds, milliseconds, microseconds, or nanoseconds since
+    /// the Unix epoch, depending on their magnitude. Events without one are timestamped with the
+    /// current time.
+    #[serde(default = "default_timestamp_field")]
+    #[configurable(metadata(docs::examples = "time"))]
+    pub timestamp_field: ConfigValuePath,
+
+    /// Whether to send the timestamp fields of events in milliseconds since the Unix epoch, instead
+    /// of as RFC 3339 strings.
+    #[serde(default)]
+    pub timestamps_as_millis: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub nan_policy: NewRelicNanPolicy,
+
+    /// The attributes sent with events, including those parsed from a JSON `message`. When
+    /// empty, all of them are sent.
+    ///
+    /// Listing an attribute also includes the attributes nested in it, such as `tags.0` for `tags`.
+    /// The `eventType` and `timestamp` of events are always sent.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "user_id", docs::examples = "tags"))]
+    pub include_attributes: Vec<String>,
+
+    /// The attributes not sent with events, including those parsed from a JSON `message`.
+    ///
+    /// Listing an attribute also excludes the attributes nested in it. It takes precedence over
+    /// `include_attributes`.
+    #[serde(default)]
+    #[configurable(metadata(docs::examples = "password", docs::examples = "headers"))]
+    pub exclude_attributes: Vec<String>,
+}
+
+fn default_event_type() -> String {
+    "VectorSink".to_owned()
+}
+
+fn default_timestamp_field() -> ConfigValuePath {
+    ConfigValuePath(owned_value_path!("timestamp"))
+}
+
+impl Default for NewRelicEventsConfig {
+    fn default() -> Self {
+        Self {
+            default_event_type: default_event_type(),
+            event_type_field: None,
+            require_event_type: false,
+            keep_raw_message: false,
+            field_precedence: NewRelicFieldPrecedence::default(),
+            timestamp_field: default_timestamp_field(),
+            timestamps_as_millis: false,
+            nan_policy: NewRelicNanPolicy::default(),
+            include_attributes: Vec::new(),
+            exclude_attributes: Vec::new(),
+        }
+    }
+}
+
+/// New Relic Logs API configuration.
+#[configurable_component]
+#[derive(Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NewRelicLogsConfig {
+    /// The `message` set on logs that don't have one.
+    #[serde(default = "default_placeholder_message")]
+    #[configurable(metadata(docs::examples = "(no message)"))]
+    pub placeholder_message: String,
+
+    /// Whether to drop logs that don't have a `message`, instead of setting the placeholder.
+    #[serde(default)]
+    pub drop_missing_message: bool,
+
+    /// The field holding the severity of logs, which is sent as their `level`.
+    #[configurable(metadata(docs::examples = "severity"))]
+    pub level_field: Option<ConfigValuePath>,
+
+    /// Whether to uppercase the severity of logs sent as their `level`.
+    #[serde(default)]
+    pub uppercase_level: bool,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub nan_policy: NewRelicNanPolicy,
+
+    /// The field holding the trace ID of logs, which is sent as their `trace.id`.
+    #[configurable(metadata(docs::examples = "trace_id"))]
+    #[configurable(metadata(docs::examples = "dd.trace_id"))]
+    pub trace_id_field: Option<ConfigValuePath>,
+
+    /// The field holding the span ID of logs, which is sent as their `span.id`.
+    #[configurable(metadata(docs::examples = "span_id"))]
+    #[configurable(metadata(docs::examples = "dd.span_id"))]
+    pub span_id_field: Option<ConfigValuePath>,
+
+    /// A prefix added to the keys of the attributes of logs, such as `custom.`, to avoid clashes
+    /// with the attributes New Relic reserves.
+    ///
+    /// The `message`, `timestamp`, `level`, `logtype`, `trace.id`, and `span.id` keys aren't
+    /// prefixed.
+    #[configurable(metadata(docs::examples = "custom."))]
+    pub attribute_prefix: Option<String>,
+
+    /// The key the logs of requests are wrapped under.
+    ///
+    /// New Relic expects `logs`, this is only meant for proxies and mock servers that expect
+    /// another one.
+    #[serde(default = "default_logs_wrapper_key")]
+    #[configurable(metadata(docs::examples = "data"))]
+    pub wrapper_key: String,
+}
+
+fn default_placeholder_message() -> String {
+    "log from vector".to_owned()
+}
+
+fn default_logs_wrapper_key() -> String {
+    LOGS_KEY.to_owned()
+}
+
+impl Default for NewRelicLogsConfig {
+    fn default() -> Self {
+        Self {
+            placeholder_message: default_placeholder_message(),
+            drop_missing_message: false,
+            level_field: None,
+            uppercase_level: false,
+            nan_policy: NewRelicNanPolicy::default(),
+            trace_id_field: None,
+            span_id_field: None,
+            attribute_prefix: None,
+            wrapper_key: default_logs_wrapper_key(),
+        }
+    }
+}
+
+const fn default_absolute_counters_ttl_secs() -> u64 {
+    3600
+}
+
+const fn default_max_payload_bytes() -> usize {
+    // New Relic rejects payloads over 1MB, keep some headroom
+    999_000
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct NewRelicDefaultBatchSettings;
 
@@ -62,12 +580,52 @@ impl RetryLogic for NewRelicApiRetry {
         // Never retry.
         false
     }
+
+    fn should_retry_response(&self, response: &Self::Response) -> RetryAction {
+        if response.halved() && !response.status_code().is_success() {
+            // Retrying would send the halves that were delivered again, and the ones that failed
+            // were retried on their own already
+            return RetryAction::DontRetry(
+                format!(
+                    "payload too large, and part of it failed with response status: {}",
+                    response.status_code()
+                )
+                .into(),
+            );
+        }
+        Self::action_for_status(response.status_code())
+    }
+}
+
+impl NewRelicApiRetry {
+    /// Returns what to do with a request the New Relic API responded to with `status`.
+    ///
+    /// Rate limited requests and server errors are retried with backoff. Payloads that are still
+    /// too large once the service has split them down to a single metric, event or log are
+    /// rejected for good, as retrying them can't succeed.
+    pub fn action_for_status(status: StatusCode) -> RetryAction {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => RetryAction::Retry("too many requests".into()),
+            StatusCode::PAYLOAD_TOO_LARGE => RetryAction::DontRetry(
+                "payload too large, reduce `max_payload_bytes` or the batch size".into(),
+            ),
+            StatusCode::NOT_IMPLEMENTED => {
+                RetryAction::DontRetry("endpoint not implemented".into())
+            }
+            _ if status.is_server_error() => {
+                RetryAction::Retry(format!("response status: {}", status).into())
+            }
+            _ if status.is_success() => RetryAction::Successful,
+            _ => RetryAction::DontRetry(format!("response status: {}", status).into()),
+        }
+    }
 }
 
 /// Configuration for the `new_relic` sink.
 #[configurable_component(sink("new_relic", "Deliver events to New Relic."))]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Derivative)]
 #[serde(deny_unknown_fields)]
+#[derivative(Default)]
 pub struct NewRelicConfig {
     /// A valid New Relic license key.
     #[configurable(metadata(docs::examples = "xxxx"))]
@@ -82,6 +640,10 @@ pub struct NewRelicConfig {
     #[configurable(derived)]
     pub region: Option<NewRelicRegion>,
 
+    /// The endpoint to send data to, instead of the one of the selected `api` and `region`.
+    #[configurable(metadata(docs::examples = "https://metric-api.eu.newrelic.com/metric/v1"))]
+    pub endpoint: Option<UriSerde>,
+
     #[configurable(derived)]
     pub api: NewRelicApi,
 
@@ -89,6 +651,15 @@ pub struct NewRelicConfig {
     #[serde(default = "Compression::gzip_default")]
     pub compression: Compression,
 
+    /// The maximum size of the JSON payload of a request, before compression.
+    ///
+    /// Batches whose payload is larger than this are split into several requests. A metric, event,
+    /// or log larger than this by itself is sent alone.
+    #[serde(default = "default_max_payload_bytes")]
+    #[derivative(Default(value = "default_max_payload_bytes()"))]
+    #[configurable(metadata(docs::type_unit = "bytes"))]
+    pub max_payload_bytes: usize,
+
     #[configurable(derived)]
     #[serde(
         default,
@@ -96,6 +667,21 @@ pub struct NewRelicConfig {
     )]
     pub encoding: Transformer,
 
+    #[serde(flatten)]
+    pub attributes: NewRelicAttributesConfig,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub metrics: NewRelicMetricsConfig,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub events: NewRelicEventsConfig,
+
+    #[configurable(derived)]
+    #[serde(default)]
+    pub logs: NewRelicLogsConfig,
+
     #[configurable(derived)]
     #[serde(default)]
     pub batch: BatchConfig<NewRelicDefaultBatchSettings>,
@@ -135,6 +721,17 @@ impl SinkConfig for NewRelicConfig {
         &self,
         cx: SinkContext,
     ) -> crate::Result<(super::VectorSink, super::Healthcheck)> {
+        validate_quantiles(&self.metrics.distribution_quantiles)?;
+        if let Some(max_value_bytes) = self.attributes.max_value_bytes {
+            if max_value_bytes < TRUNCATION_MARKER.len() {
+                return Err(format!(
+                    "`attributes.max_value_bytes` must be at least {} bytes, to fit the truncation marker",
+                    TRUNCATION_MARKER.len()
+                )
+                .into());
+            }
+        }
+
         let batcher_settings = self
             .batch
             .validate()?
@@ -142,6 +739,7 @@ impl SinkConfig for NewRelicConfig {
             .into_batcher_settings()?;
 
         let request_limits = self.request.unwrap_with(&Default::default());
+        let half_retry_policy = request_limits.retry_policy(NewRelicApiRetry);
         let tls_settings = TlsSettings::from_options(&None)?;
         let client = HttpClient::new(tls_settings, &cx.proxy)?;
         let credentials = Arc::from(NewRelicCredentials::from(self));
@@ -150,16 +748,24 @@ impl SinkConfig for NewRelicConfig {
 
         let service = ServiceBuilder::new()
             .settings(request_limits, NewRelicApiRetry)
-            .service(NewRelicApiService { client });
+            .service(NewRelicApiService {
+                client,
+                half_retry_policy,
+            });
 
         let sink = NewRelicSink {
             service,
             encoder: NewRelicEncoder {
                 transformer: self.encoding.clone(),
                 credentials: Arc::clone(&credentials),
+                attributes: self.attributes.clone(),
+                metrics: self.metrics.clone(),
+                events: self.events.clone(),
+                logs: self.logs.clone(),
             },
             credentials,
             compression: self.compression,
+            max_payload_bytes: self.max_payload_bytes,
             batcher_settings,
         };
 
@@ -226,7 +832,12 @@ impl From<&NewRelicConfig> for NewRelicCredentials {
             account_id: config.account_id.inner().to_string(),
             api: config.api,
             region: config.region.unwrap_or(NewRelicRegion::Us),
-            override_uri: config.override_uri.clone(),
+            override_uri: config.override_uri.clone().or_else(|| {
+                config
+                    .endpoint
+                    .as_ref()
+                    .map(|endpoint| endpoint.uri.clone())
+            }),
         }
     }
 }
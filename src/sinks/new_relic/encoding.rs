@@ -1,12 +1,16 @@
 use std::{io, sync::Arc};
 
 use serde::Serialize;
-use vector_common::request_metadata::GroupedCountByteSize;
-use vector_core::{config::telemetry, event::Event, EstimatedJsonEncodedSizeOf};
+use vector_common::{
+    internal_event::{ComponentEventsDropped, UNINTENTIONAL},
+    request_metadata::GroupedCountByteSize,
+};
+use vector_core::{config::telemetry, event::Event, ByteSizeOf, EstimatedJsonEncodedSizeOf};
 
 use super::{
-    EventsApiModel, LogsApiModel, MetricsApiModel, NewRelicApi, NewRelicApiModel,
-    NewRelicCredentials, NewRelicSinkError,
+    EventsApiModel, KeyValData, LogsApiModel, MetricsApiModel, NewRelicApi, NewRelicApiModel,
+    NewRelicAttributesConfig, NewRelicCredentials, NewRelicEventsConfig, NewRelicLogsConfig,
+    NewRelicMetricsConfig, NewRelicSinkError,
 };
 use crate::sinks::{
     prelude::*,
@@ -16,14 +20,181 @@ use crate::sinks::{
 pub struct NewRelicEncoder {
     pub(super) transformer: Transformer,
     pub(super) credentials: Arc<NewRelicCredentials>,
+    pub(super) attributes: NewRelicAttributesConfig,
+    pub(super) metrics: NewRelicMetricsConfig,
+    pub(super) events: NewRelicEventsConfig,
+    pub(super) logs: NewRelicLogsConfig,
 }
 
-impl Encoder<Vec<Event>> for NewRelicEncoder {
-    fn encode_input(
+/// A part of a batch, encoded into a JSON payload of its own.
+///
+/// The payload comes with the model it was encoded from so that it can be split further if New
+/// Relic finds it too large, and with the finalizers of the events it holds.
+pub struct NewRelicChunk {
+    pub model: NewRelicApiModel,
+    pub json: Vec<u8>,
+    pub finalizers: EventFinalizers,
+    pub event_count: usize,
+    pub events_byte_size: usize,
+    pub json_size: GroupedCountByteSize,
+}
+
+/// The events of a chunk gathered so far.
+struct ChunkBuilder {
+    data: Vec<KeyValData>,
+    size: usize,
+    finalizers: EventFinalizers,
+    event_count: usize,
+    events_byte_size: usize,
+    json_size: GroupedCountByteSize,
+}
+
+impl ChunkBuilder {
+    fn new(empty_size: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            // The first data isn't preceded by a comma
+            size: empty_size - 1,
+            finalizers: EventFinalizers::default(),
+            event_count: 0,
+            events_byte_size: 0,
+            json_size: telemetry().create_request_count_byte_size(),
+        }
+    }
+}
+
+impl NewRelicEncoder {
+    /// Encodes the events into JSON payloads of at most `max_bytes`, splitting them into several
+    /// chunks if needed.
+    ///
+    /// The size is that of the data sent, which is larger than the events themselves, as
+    /// distributions are sent along with the gauges of their quantiles, and attributes are added
+    /// to the data. An event whose data is larger than `max_bytes` by itself is sent on its own.
+    pub fn encode_chunks(
         &self,
         mut input: Vec<Event>,
-        writer: &mut dyn io::Write,
-    ) -> io::Result<(usize, GroupedCountByteSize)> {
+        max_bytes: usize,
+    ) -> Result<Vec<NewRelicChunk>, NewRelicSinkError> {
+        let mut sizes = Vec::with_capacity(input.len());
+        let mut finalizers = Vec::with_capacity(input.len());
+        for event in input.iter_mut() {
+            let byte_size = event.size_of();
+            finalizers.push(event.take_finalizers());
+            self.transformer.transform(event);
+            let mut json_size = telemetry().create_request_count_byte_size();
+            json_size.add_event(event, event.estimated_json_encoded_size_of());
+            sizes.push((byte_size, json_size));
+        }
+
+        let count = input.len();
+        let data = match self.to_api_data(input) {
+            Ok(data) => data,
+            Err(error) => {
+                // The whole batch fails on a NaN value, so none of its events are sent
+                for finalizers in finalizers {
+                    finalizers.update_status(EventStatus::Rejected);
+                }
+                emit!(ComponentEventsDropped::<UNINTENTIONAL> {
+                    count,
+                    reason: "NaN value not supported"
+                });
+                return Err(error);
+            }
+        };
+        if data.is_empty() {
+            return Err(match self.credentials.api {
+                NewRelicApi::Events => NewRelicSinkError::NoValidEvents,
+                NewRelicApi::Metrics => NewRelicSinkError::NoValidMetrics,
+                NewRelicApi::Logs => NewRelicSinkError::NoValidLogs,
+            });
+        }
+        // The data of a chunk is added to the JSON of a payload without any
+        let empty_size = model_to_json(&self.to_model(Vec::new()))?.len();
+
+        let mut data = data.into_iter().peekable();
+        let mut chunks = Vec::new();
+        let mut chunk = ChunkBuilder::new(empty_size);
+        for (index, ((byte_size, json_size), finalizers)) in
+            sizes.into_iter().zip(finalizers).enumerate()
+        {
+            let mut event_data = Vec::new();
+            let mut event_size = 0;
+            while let Some((_, item)) = data.next_if(|(item_index, _)| *item_index == index) {
+                // Along with the comma separating it from the other data
+                event_size += serde_json::to_vec(&item)
+                    .map_err(|source| NewRelicSinkError::Json { source })?
+                    .len()
+                    + 1;
+                event_data.push(item);
+            }
+
+            if !chunk.data.is_empty()
+                && !event_data.is_empty()
+                && chunk.size + event_size > max_bytes
+            {
+                chunks.push(self.finish_chunk(chunk)?);
+                chunk = ChunkBuilder::new(empty_size);
+            }
+            chunk.data.extend(event_data);
+            chunk.size += event_size;
+            chunk.finalizers.merge(finalizers);
+            chunk.event_count += 1;
+            chunk.events_byte_size += byte_size;
+            chunk.json_size += json_size;
+        }
+        chunks.push(self.finish_chunk(chunk)?);
+
+        Ok(chunks)
+    }
+
+    fn finish_chunk(&self, chunk: ChunkBuilder) -> Result<NewRelicChunk, NewRelicSinkError> {
+        let model = self.to_model(chunk.data);
+        let json = model_to_json(&model)?;
+
+        Ok(NewRelicChunk {
+            model,
+            json,
+            finalizers: chunk.finalizers,
+            event_count: chunk.event_count,
+            events_byte_size: chunk.events_byte_size,
+            json_size: chunk.json_size,
+        })
+    }
+
+    fn to_api_data(
+        &self,
+        input: Vec<Event>,
+    ) -> Result<Vec<(usize, KeyValData)>, NewRelicSinkError> {
+        match self.credentials.api {
+            NewRelicApi::Events => {
+                EventsApiModel::try_data_from_events(input, &self.events, &self.attributes)
+            }
+            NewRelicApi::Metrics => {
+                MetricsApiModel::try_data_from_events(input, &self.metrics, &self.attributes)
+            }
+            NewRelicApi::Logs => {
+                LogsApiModel::try_data_from_events(input, &self.logs, &self.attributes)
+            }
+        }
+    }
+
+    fn to_model(&self, data: Vec<KeyValData>) -> NewRelicApiModel {
+        match self.credentials.api {
+            NewRelicApi::Events => NewRelicApiModel::Events(EventsApiModel::new(data)),
+            NewRelicApi::Metrics => NewRelicApiModel::Metrics(MetricsApiModel::with_key(
+                &self.metrics.wrapper_key,
+                data,
+            )),
+            NewRelicApi::Logs => {
+                NewRelicApiModel::Logs(LogsApiModel::with_key(&self.logs.wrapper_key, data))
+            }
+        }
+    }
+
+    fn to_api_model(
+        &self,
+        mut input: Vec<Event>,
+    ) -> Result<(NewRelicApiModel, GroupedCountByteSize), NewRelicSinkError> {
         let mut byte_size = telemetry().create_request_count_byte_size();
 
         for event in input.iter_mut() {
@@ -31,17 +202,35 @@ impl Encoder<Vec<Event>> for NewRelicEncoder {
             byte_size.add_event(event, event.estimated_json_encoded_size_of());
         }
 
-        let api_model = match self.credentials.api {
-            NewRelicApi::Events => NewRelicApiModel::Events(EventsApiModel::try_from(input)?),
-            NewRelicApi::Metrics => NewRelicApiModel::Metrics(MetricsApiModel::try_from(input)?),
-            NewRelicApi::Logs => NewRelicApiModel::Logs(LogsApiModel::try_from(input)?),
-        };
+        let api_model =
+            match self.credentials.api {
+                NewRelicApi::Events => NewRelicApiModel::Events(EventsApiModel::try_from_events(
+                    input,
+                    &self.events,
+                    &self.attributes,
+                )?),
+                NewRelicApi::Metrics => NewRelicApiModel::Metrics(
+                    MetricsApiModel::try_from_events(input, &self.metrics, &self.attributes)?,
+                ),
+                NewRelicApi::Logs => NewRelicApiModel::Logs(LogsApiModel::try_from_events(
+                    input,
+                    &self.logs,
+                    &self.attributes,
+                )?),
+            };
 
-        let json = match api_model {
-            NewRelicApiModel::Events(ev_api_model) => to_json(&ev_api_model)?,
-            NewRelicApiModel::Metrics(met_api_model) => to_json(&met_api_model)?,
-            NewRelicApiModel::Logs(log_api_model) => to_json(&log_api_model)?,
-        };
+        Ok((api_model, byte_size))
+    }
+}
+
+impl Encoder<Vec<Event>> for NewRelicEncoder {
+    fn encode_input(
+        &self,
+        input: Vec<Event>,
+        writer: &mut dyn io::Write,
+    ) -> io::Result<(usize, GroupedCountByteSize)> {
+        let (api_model, byte_size) = self.to_api_model(input)?;
+        let json = model_to_json(&api_model)?;
 
         let size = as_tracked_write::<_, _, io::Error>(writer, &json, |writer, json| {
             writer.write_all(json)?;
@@ -52,15 +241,20 @@ impl Encoder<Vec<Event>> for NewRelicEncoder {
     }
 }
 
+pub fn model_to_json(api_model: &NewRelicApiModel) -> Result<Vec<u8>, NewRelicSinkError> {
+    match api_model {
+        NewRelicApiModel::Events(ev_api_model) => to_json(ev_api_model),
+        NewRelicApiModel::Metrics(met_api_model) => to_json(met_api_model),
+        NewRelicApiModel::Logs(log_api_model) => to_json(log_api_model),
+    }
+}
+
 pub fn to_json<T: Serialize>(model: &T) -> Result<Vec<u8>, NewRelicSinkError> {
     match serde_json::to_vec(model) {
         Ok(mut json) => {
             json.push(b'\n');
             Ok(json)
         }
-        Err(error) => Err(NewRelicSinkError::new(&format!(
-            "Failed generating JSON: {}",
-            error
-        ))),
+        Err(source) => Err(NewRelicSinkError::Json { source }),
     }
 }
@@ -2,12 +2,14 @@ mod config;
 mod encoding;
 mod healthcheck;
 mod model;
+mod normalizer;
 mod service;
 mod sink;
 
 pub use config::*;
 pub use encoding::*;
 pub use model::*;
+pub use normalizer::*;
 pub use service::*;
 pub use sink::*;
 
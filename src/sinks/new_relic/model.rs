@@ -5,75 +5,225 @@ use std::{
     time::SystemTime,
 };
 
+use base64::prelude::{Engine as _, BASE64_STANDARD};
 use chrono::{DateTime, Utc};
-use ordered_float::NotNan;
+use ordered_float::{FloatIsNan, NotNan};
 use serde::{Deserialize, Serialize};
 use vector_common::internal_event::{ComponentEventsDropped, INTENTIONAL, UNINTENTIONAL};
-use vrl::event_path;
+use vrl::{event_path, path::PathPrefix};
 
-use super::NewRelicSinkError;
-use crate::event::{Event, MetricKind, MetricValue, Value};
+use super::{
+    NewRelicAttributeTransformer, NewRelicAttributesConfig, NewRelicBinaryEncoding,
+    NewRelicEventsConfig, NewRelicFieldPrecedence, NewRelicLogsConfig, NewRelicMetricsConfig,
+    NewRelicMissingIntervalPolicy, NewRelicNanPolicy, NewRelicSampleRatePolicy, NewRelicSinkError,
+};
+use crate::{
+    event::{
+        metric::{MetricSeries, Quantile, Sample},
+        Event, MetricKind, MetricValue, Value,
+    },
+    sinks::util::statistic::DistributionStatistic,
+};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum NewRelicApiModel {
     Metrics(MetricsApiModel),
     Events(EventsApiModel),
     Logs(LogsApiModel),
 }
 
-type KeyValData = HashMap<String, Value>;
-type DataStore = HashMap<String, Vec<KeyValData>>;
+impl NewRelicApiModel {
+    /// Splits the model into two models holding half of its data each.
+    ///
+    /// Returns the model as it is if it holds a single metric, event or log, as it can't be split
+    /// any further.
+    pub fn halve(self) -> Result<(Self, Self), Self> {
+        match self {
+            Self::Metrics(model) => {
+                let (key, data) = take_data(model.0, METRICS_KEY);
+                halve_data(data)
+                    .map(|(first, second)| {
+                        (
+                            Self::Metrics(MetricsApiModel::with_key(&key, first)),
+                            Self::Metrics(MetricsApiModel::with_key(&key, second)),
+                        )
+                    })
+                    .map_err(|data| Self::Metrics(MetricsApiModel::with_key(&key, data)))
+            }
+            Self::Events(model) => halve_data(model.0)
+                .map(|(first, second)| {
+                    (
+                        Self::Events(EventsApiModel::new(first)),
+                        Self::Events(EventsApiModel::new(second)),
+                    )
+                })
+                .map_err(|data| Self::Events(EventsApiModel::new(data))),
+            Self::Logs(model) => {
+                let (key, data) = take_data(model.0, LOGS_KEY);
+                halve_data(data)
+                    .map(|(first, second)| {
+                        (
+                            Self::Logs(LogsApiModel::with_key(&key, first)),
+                            Self::Logs(LogsApiModel::with_key(&key, second)),
+                        )
+                    })
+                    .map_err(|data| Self::Logs(LogsApiModel::with_key(&key, data)))
+            }
+        }
+    }
+}
+
+fn halve_data(
+    mut data: Vec<KeyValData>,
+) -> Result<(Vec<KeyValData>, Vec<KeyValData>), Vec<KeyValData>> {
+    if data.len() < 2 {
+        return Err(data);
+    }
+    let second = data.split_off(data.len() / 2);
+    Ok((data, second))
+}
+
+// Ordered maps keep the serialized JSON the same from one run to the next
+pub type KeyValData = BTreeMap<String, Value>;
+type DataStore = BTreeMap<String, Vec<KeyValData>>;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The key New Relic expects the data of the Metrics API to be wrapped under.
+pub const METRICS_KEY: &str = "metrics";
+
+/// The key New Relic expects the data of the Logs API to be wrapped under.
+pub const LOGS_KEY: &str = "logs";
+
+/// Takes the data out of the stores, along with the key it is wrapped under, which is `default_key`
+/// if the stores are empty.
+fn take_data(stores: Vec<DataStore>, default_key: &str) -> (String, Vec<KeyValData>) {
+    let mut key = None;
+    let mut data = Vec::new();
+    for store in stores {
+        for (store_key, store_data) in store {
+            key.get_or_insert(store_key);
+            data.extend(store_data);
+        }
+    }
+    (key.unwrap_or_else(|| default_key.to_owned()), data)
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct MetricsApiModel(pub Vec<DataStore>);
 
 impl MetricsApiModel {
     pub fn new(metric_array: Vec<KeyValData>) -> Self {
+        Self::with_key(METRICS_KEY, metric_array)
+    }
+
+    /// Wraps the metrics under `key`, instead of the one New Relic expects.
+    pub fn with_key(key: &str, metric_array: Vec<KeyValData>) -> Self {
         let mut metric_store = DataStore::new();
-        metric_store.insert("metrics".to_owned(), metric_array);
+        metric_store.insert(key.to_owned(), metric_array);
         Self(vec![metric_store])
     }
-}
 
-impl TryFrom<Vec<Event>> for MetricsApiModel {
-    type Error = NewRelicSinkError;
+    pub fn try_from_events(
+        buf_events: Vec<Event>,
+        config: &NewRelicMetricsConfig,
+        attributes_config: &NewRelicAttributesConfig,
+    ) -> Result<Self, NewRelicSinkError> {
+        let metric_array: Vec<_> =
+            Self::try_data_from_events(buf_events, config, attributes_config)?
+                .into_iter()
+                .map(|(_, metric)| metric)
+                .collect();
 
-    fn try_from(buf_events: Vec<Event>) -> Result<Self, Self::Error> {
+        if !metric_array.is_empty() {
+            Ok(Self::with_key(&config.wrapper_key, metric_array))
+        } else {
+            Err(NewRelicSinkError::NoValidMetrics)
+        }
+    }
+
+    /// Converts the events into the data of metrics, each along with the position of the event it
+    /// comes from.
+    ///
+    /// An event may be sent as several metrics, such as a distribution and the gauges of its
+    /// quantiles, or as none if it is dropped.
+    pub fn try_data_from_events(
+        buf_events: Vec<Event>,
+        config: &NewRelicMetricsConfig,
+        attributes_config: &NewRelicAttributesConfig,
+    ) -> Result<Vec<(usize, KeyValData)>, NewRelicSinkError> {
         let mut num_non_metric_events = 0;
         let mut num_missing_interval = 0;
         let mut num_nan_value = 0;
         let mut num_unsupported_metric_type = 0;
+        let nan_policy = config.nan_policy;
+        let scale = |value: f64| {
+            config
+                .value_multiplier
+                .map_or(value, |factor| value * factor)
+        };
 
         let metric_array: Vec<_> = buf_events
             .into_iter()
-            .filter_map(|event| {
+            .enumerate()
+            .filter_map(|(index, event)| {
                 let Some(metric) = event.try_into_metric() else {
                     num_non_metric_events += 1;
                     return None;
                 };
 
                 // Generate Value::Object() from BTreeMap<String, String>
-                let (series, data, _) = metric.into_parts();
+                let (mut series, data, metadata) = metric.into_parts();
 
                 let mut metric_data = KeyValData::new();
+                let mut quantile_gauges = Vec::new();
+
+                // Only counters are sampled
+                let sample_rate = match (config.sample_rate_policy, &data.value) {
+                    (NewRelicSampleRatePolicy::Ignore, _) => None,
+                    (_, MetricValue::Counter { .. }) => {
+                        take_sample_rate(metadata.value(), &mut series)
+                    }
+                    _ => None,
+                };
+                let unsample = |value: f64| match sample_rate {
+                    Some(rate) if config.sample_rate_policy == NewRelicSampleRatePolicy::Scale => {
+                        value / rate.into_inner()
+                    }
+                    _ => value,
+                };
 
-                // We only handle gauge and counter metrics
+                // We only handle gauge, counter, set, distribution, histogram and summary metrics
                 // Extract value & type and set type-related attributes
-                let (value, metric_type) = match (data.value, &data.kind) {
+                let (value, mut metric_type) = match (data.value, &data.kind) {
                     (MetricValue::Counter { value }, MetricKind::Incremental) => {
-                        let Some(interval_ms) = data.time.interval_ms else {
-                            // Incremental counter without an interval is worthless, skip this metric
-                            num_missing_interval += 1;
-                            return None;
-                        };
-                        metric_data.insert(
-                            "interval.ms".to_owned(),
-                            Value::from(interval_ms.get() as i64),
-                        );
-                        (value, "count")
+                        (scalar_value(scale(unsample(value)), nan_policy), "count")
+                    }
+                    (MetricValue::Counter { value }, MetricKind::Absolute) => {
+                        (scalar_value(scale(unsample(value)), nan_policy), "gauge")
+                    }
+                    (MetricValue::Gauge { value }, _) => {
+                        (scalar_value(scale(value), nan_policy), "gauge")
+                    }
+                    (MetricValue::Set { values }, _) => (Some(Value::from(values.len())), "gauge"),
+                    (MetricValue::Distribution { samples, .. }, _) => {
+                        quantile_gauges =
+                            distribution_quantiles(&samples, &config.distribution_quantiles);
+                        (distribution_summary_value(&samples, nan_policy), "summary")
+                    }
+                    (MetricValue::AggregatedHistogram { count, sum, .. }, _) => {
+                        (summary_value(count, sum, None, None, nan_policy), "summary")
+                    }
+                    (
+                        MetricValue::AggregatedSummary {
+                            quantiles,
+                            count,
+                            sum,
+                        },
+                        _,
+                    ) => {
+                        let (min, max, quantiles) = split_quantiles(quantiles);
+                        quantile_gauges = quantiles;
+                        (summary_value(count, sum, min, max, nan_policy), "summary")
                     }
-                    (MetricValue::Counter { value }, MetricKind::Absolute) => (value, "gauge"),
-                    (MetricValue::Gauge { value }, _) => (value, "gauge"),
                     _ => {
                         // Unsupported metric type
                         num_unsupported_metric_type += 1;
@@ -81,38 +231,201 @@ impl TryFrom<Vec<Event>> for MetricsApiModel {
                     }
                 };
 
+                // Counts and summaries are aggregated over an interval, which gauges may also have
+                match data.time.interval_ms {
+                    Some(interval_ms) => {
+                        metric_data.insert(
+                            "interval.ms".to_owned(),
+                            Value::from(interval_ms.get() as i64),
+                        );
+                    }
+                    None if metric_type != "gauge" && data.kind == MetricKind::Incremental => {
+                        match config.missing_interval_policy {
+                            NewRelicMissingIntervalPolicy::SynthesizeInterval => {
+                                metric_data.insert(
+                                    "interval.ms".to_owned(),
+                                    Value::from(
+                                        config.synthetic_interval_secs.saturating_mul(1000) as i64,
+                                    ),
+                                );
+                            }
+                            NewRelicMissingIntervalPolicy::AsGauge if metric_type == "count" => {
+                                metric_type = "gauge";
+                            }
+                            _ => {
+                                // Incremental metric without an interval is worthless, skip this
+                                // metric
+                                num_missing_interval += 1;
+                                return None;
+                            }
+                        }
+                    }
+                    None => {}
+                }
+
                 // Set name, type, value, timestamp, and attributes
-                metric_data.insert("name".to_owned(), Value::from(series.name.name));
-                metric_data.insert("type".to_owned(), Value::from(metric_type));
-                let Some(value) = NotNan::new(value).ok() else {
+                let name = match series.name.namespace {
+                    Some(namespace) if config.prefix_namespace => {
+                        format!("{}.{}", namespace, series.name.name)
+                    }
+                    _ => series.name.name,
+                };
+                let name = if config.sanitize_names {
+                    sanitize_metric_name(&name)
+                } else {
+                    name
+                };
+                metric_data.insert("name".to_owned(), Value::from(name.as_str()));
+                // New Relic takes metrics without a type for gauges
+                if !(config.omit_gauge_type && metric_type == "gauge") {
+                    metric_data.insert("type".to_owned(), Value::from(metric_type));
+                }
+                let Some(value) = value else {
                     num_nan_value += 1;
                     return None;
                 };
-                metric_data.insert("value".to_owned(), Value::from(value));
+                metric_data.insert("value".to_owned(), value);
+                let now = DateTime::<Utc>::from(SystemTime::now());
+                let mut timestamp = data.time.timestamp.unwrap_or(now);
+                if let Some(max_future_secs) = config.max_future_secs {
+                    if timestamp > now + chrono::Duration::seconds(max_future_secs as i64) {
+                        warn!(
+                            message = "Metric timestamp is too far in the future, clamping to now.",
+                            metric = %name,
+                            %timestamp,
+                            max_future_secs,
+                            internal_log_rate_limit = true
+                        );
+                        timestamp = now;
+                    }
+                }
                 metric_data.insert(
                     "timestamp".to_owned(),
-                    Value::from(
-                        data.time
-                            .timestamp
-                            .unwrap_or_else(|| DateTime::<Utc>::from(SystemTime::now()))
-                            .timestamp(),
-                    ),
+                    Value::from(timestamp.timestamp_millis()),
                 );
+                let mut attributes = BTreeMap::new();
+                if config.metadata_attributes {
+                    if let Value::Object(fields) = metadata.value() {
+                        for (key, value) in fields {
+                            flatten_attribute(
+                                &mut attributes,
+                                key.clone(),
+                                value.clone(),
+                                &config.flatten_separator,
+                            );
+                        }
+                    }
+                }
                 if let Some(tags) = series.tags {
-                    metric_data.insert(
-                        "attributes".to_owned(),
-                        Value::from(
-                            tags.iter_single()
-                                .map(|(key, value)| (key.to_string(), Value::from(value)))
-                                .collect::<BTreeMap<_, _>>(),
-                        ),
+                    // Tags holding several values are sent as an array attribute, bare
+                    // values are skipped.
+                    attributes.extend(tags.iter_sets().filter_map(|(key, value_set)| {
+                        let mut values: Vec<Value> =
+                            value_set.iter().flatten().map(Value::from).collect();
+                        let value = match values.len() {
+                            0 => return None,
+                            1 => values.remove(0),
+                            _ => Value::Array(values),
+                        };
+                        Some((key.to_string(), value))
+                    }));
+                }
+                // Only the attributes of the metric are transformed, the ones set by the sink
+                // are kept as New Relic expects them
+                attributes = transform_attributes(
+                    attributes,
+                    &attributes_config.attribute_transformers,
+                    &[],
+                );
+                // The unit is taken from the metadata, falling back to the configured one
+                let unit = match metadata.value() {
+                    Value::Object(fields) => fields.get("unit").cloned(),
+                    _ => None,
+                }
+                .or_else(|| config.default_unit.as_deref().map(Value::from));
+                if let Some(unit) = unit {
+                    attributes.entry("unit".to_owned()).or_insert(unit);
+                }
+                if config.sample_rate_policy == NewRelicSampleRatePolicy::Attribute {
+                    if let Some(rate) = sample_rate {
+                        attributes
+                            .entry("sampleRate".to_owned())
+                            .or_insert(Value::from(rate));
+                    }
+                }
+                if config.metric_kind_attribute {
+                    let kind = match data.kind {
+                        MetricKind::Incremental => "incremental",
+                        MetricKind::Absolute => "absolute",
+                    };
+                    attributes.insert("vector.metric_kind".to_owned(), Value::from(kind));
+                }
+                for (key, value) in default_attributes(attributes_config) {
+                    attributes.entry(key).or_insert(value);
+                }
+                if config.sanitize_attribute_keys {
+                    attributes = attributes
+                        .into_iter()
+                        .map(|(key, value)| (sanitize_attribute_key(key), value))
+                        .collect();
+                }
+                if let Some(max_attributes) = config.max_attributes {
+                    if attributes.len() > max_attributes {
+                        warn!(
+                            message = "Metric has too many attributes, truncating.",
+                            metric = %name,
+                            count = attributes.len(),
+                            max_attributes,
+                            internal_log_rate_limit = true
+                        );
+                        // Attributes are sorted by key, so the same ones are kept every time
+                        attributes = attributes.into_iter().take(max_attributes).collect();
+                    }
+                }
+                for value in attributes.values_mut() {
+                    encode_binary(value, attributes_config.binary_encoding);
+                    truncate_value(value, attributes_config.max_value_bytes);
+                }
+                if !replace_non_finite(attributes.values_mut(), nan_policy) {
+                    num_nan_value += 1;
+                    return None;
+                }
+                if !attributes.is_empty() {
+                    metric_data.insert("attributes".to_owned(), Value::from(attributes));
+                }
+
+                // Quantiles that don't fit into the summary are sent as separate gauges
+                let mut metrics = Vec::with_capacity(1 + quantile_gauges.len());
+                for quantile in quantile_gauges {
+                    let Some(value) = scalar_value(quantile.value, nan_policy) else {
+                        num_nan_value += 1;
+                        return None;
+                    };
+                    let mut gauge = metric_data.clone();
+                    gauge.remove("interval.ms");
+                    gauge.insert(
+                        "name".to_owned(),
+                        Value::from(format!("{}.p{}", name, quantile.to_percentile_string())),
                     );
+                    if config.omit_gauge_type {
+                        gauge.remove("type");
+                    } else {
+                        gauge.insert("type".to_owned(), Value::from("gauge"));
+                    }
+                    gauge.insert("value".to_owned(), value);
+                    metrics.push(gauge);
                 }
+                metrics.insert(0, metric_data);
 
-                Some(metric_data)
+                Some(metrics.into_iter().map(move |metric| (index, metric)))
             })
+            .flatten()
             .collect();
 
+        if num_nan_value > 0 && nan_policy == NewRelicNanPolicy::Error {
+            return Err(NewRelicSinkError::NanValue);
+        }
+
         if num_non_metric_events > 0 {
             emit!(ComponentEventsDropped::<INTENTIONAL> {
                 count: num_non_metric_events,
@@ -134,37 +447,446 @@ impl TryFrom<Vec<Event>> for MetricsApiModel {
         if num_missing_interval > 0 {
             emit!(ComponentEventsDropped::<UNINTENTIONAL> {
                 count: num_missing_interval,
-                reason: "incremental counter missing interval"
+                reason: "incremental metric missing interval"
             });
         }
 
-        if !metric_array.is_empty() {
-            Ok(Self::new(metric_array))
+        Ok(metric_array)
+    }
+}
+
+impl TryFrom<Vec<Event>> for MetricsApiModel {
+    type Error = NewRelicSinkError;
+
+    fn try_from(buf_events: Vec<Event>) -> Result<Self, Self::Error> {
+        Self::try_from_events(
+            buf_events,
+            &NewRelicMetricsConfig::default(),
+            &NewRelicAttributesConfig::default(),
+        )
+    }
+}
+
+/// Returns the attributes set on data that doesn't already have them.
+fn default_attributes(
+    attributes_config: &NewRelicAttributesConfig,
+) -> impl Iterator<Item = (String, Value)> + '_ {
+    let host = attributes_config
+        .default_host
+        .iter()
+        .map(|host| ("host".to_owned(), Value::from(host.as_str())));
+    let attributes = attributes_config
+        .default_attributes
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::from(value.as_str())));
+    host.chain(attributes)
+}
+
+/// Applies the transformers, in order, to the attributes whose keys aren't reserved.
+fn transform_attributes(
+    attributes: KeyValData,
+    transformers: &[NewRelicAttributeTransformer],
+    reserved_keys: &[&str],
+) -> KeyValData {
+    transformers
+        .iter()
+        .fold(attributes, |attributes, transformer| {
+            attributes
+                .into_iter()
+                .map(|(key, value)| {
+                    if reserved_keys.contains(&key.as_str()) {
+                        return (key, value);
+                    }
+                    match transformer {
+                        NewRelicAttributeTransformer::LowercaseKeys => (key.to_lowercase(), value),
+                        NewRelicAttributeTransformer::TrimValues => {
+                            let trimmed = value.as_str().and_then(|text| {
+                                let trimmed = text.trim();
+                                (trimmed.len() != text.len()).then(|| trimmed.to_owned())
+                            });
+                            (key, trimmed.map_or(value, Value::from))
+                        }
+                        NewRelicAttributeTransformer::RenameMap(rename) if key == rename.key => {
+                            let renamed = value
+                                .as_str()
+                                .and_then(|text| rename.values.get(text.as_ref()).cloned());
+                            (key, renamed.map_or(value, Value::from))
+                        }
+                        NewRelicAttributeTransformer::RenameMap(_) => (key, value),
+                    }
+                })
+                .collect()
+        })
+}
+
+/// Encodes values that aren't valid UTF-8 as text.
+fn encode_binary(value: &mut Value, encoding: NewRelicBinaryEncoding) {
+    if let Value::Bytes(bytes) = value {
+        if std::str::from_utf8(bytes).is_err() {
+            let encoded = match encoding {
+                NewRelicBinaryEncoding::LossyUtf8 => String::from_utf8_lossy(bytes).into_owned(),
+                NewRelicBinaryEncoding::Base64 => BASE64_STANDARD.encode(bytes),
+            };
+            *value = Value::from(encoded);
+        }
+    }
+}
+
+/// The marker ending truncated values.
+pub const TRUNCATION_MARKER: &str = "...";
+
+/// Truncates text longer than `max_bytes`, ending it with `...` to show that it was cut.
+///
+/// The length is that of the text sent, which is longer than the value when it isn't valid UTF-8.
+/// Limits too short for the marker are rejected by the config, but would cut the text without it.
+fn truncate_value(value: &mut Value, max_bytes: Option<usize>) {
+    let Some(max_bytes) = max_bytes else {
+        return;
+    };
+    if let Value::Bytes(bytes) = value {
+        let text = String::from_utf8_lossy(bytes);
+        if text.len() > max_bytes {
+            let (mut end, marker) = match max_bytes.checked_sub(TRUNCATION_MARKER.len()) {
+                Some(end) => (end, TRUNCATION_MARKER),
+                None => (max_bytes, ""),
+            };
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            *value = Value::from(format!("{}{}", &text[..end], marker));
+        }
+    }
+}
+
+/// Returns the sample rate of a counter, taken from the `sample_rate` of its metadata or, failing
+/// that, removed from its tags.
+///
+/// Rates that aren't within `(0, 1]` are ignored.
+fn take_sample_rate(metadata: &Value, series: &mut MetricSeries) -> Option<NotNan<f64>> {
+    let from_metadata = match metadata {
+        Value::Object(fields) => match fields.get("sample_rate") {
+            Some(Value::Float(rate)) => Some(rate.into_inner()),
+            Some(Value::Integer(rate)) => Some(*rate as f64),
+            Some(Value::Bytes(rate)) => String::from_utf8_lossy(rate).parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    let from_tags = series
+        .tags
+        .as_mut()
+        .and_then(|tags| tags.remove("sample_rate"))
+        .and_then(|rate| rate.parse().ok());
+    from_metadata
+        .or(from_tags)
+        .filter(|rate| *rate > 0.0 && *rate <= 1.0)
+        .and_then(|rate| NotNan::new(rate).ok())
+}
+
+/// The maximum length of New Relic metric names, in characters.
+const MAX_METRIC_NAME_LENGTH: usize = 255;
+
+/// Replaces the characters of a metric name that New Relic doesn't accept with `_`, and truncates
+/// it to the length New Relic accepts.
+fn sanitize_metric_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | ':' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_METRIC_NAME_LENGTH)
+        .collect()
+}
+
+/// The keys of the fields set on New Relic metrics.
+const RESERVED_METRIC_KEYS: [&str; 5] = ["name", "type", "value", "timestamp", "interval.ms"];
+
+/// Prefixes attribute keys that clash with the fields of New Relic metrics with `tag.`.
+fn sanitize_attribute_key(key: String) -> String {
+    if RESERVED_METRIC_KEYS.contains(&key.as_str()) {
+        format!("tag.{}", key)
+    } else {
+        key
+    }
+}
+
+/// Inserts an attribute, flattening nested objects and arrays into scalar attributes whose keys
+/// are joined with the separator, as New Relic only supports scalar attribute values.
+fn flatten_attribute(
+    attributes: &mut BTreeMap<String, Value>,
+    key: String,
+    value: Value,
+    separator: &str,
+) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                flatten_attribute(
+                    attributes,
+                    format!("{}{}{}", key, separator, field),
+                    value,
+                    separator,
+                );
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                flatten_attribute(
+                    attributes,
+                    format!("{}{}{}", key, separator, index),
+                    value,
+                    separator,
+                );
+            }
+        }
+        value => {
+            attributes.insert(key, value);
+        }
+    }
+}
+
+/// Applies the NaN policy to a value, returning `None` if it is NaN or infinite and isn't replaced.
+fn not_nan(value: f64, nan_policy: NewRelicNanPolicy) -> Option<NotNan<f64>> {
+    match NotNan::new(value) {
+        Ok(value) if value.is_finite() => Some(value),
+        _ if nan_policy == NewRelicNanPolicy::Zero => Some(NotNan::default()),
+        _ => None,
+    }
+}
+
+/// Applies the NaN policy to the NaN and infinite values among the values, returning `false` if
+/// any of them isn't replaced.
+fn replace_non_finite<'a>(
+    values: impl IntoIterator<Item = &'a mut Value>,
+    nan_policy: NewRelicNanPolicy,
+) -> bool {
+    for value in values {
+        if let Value::Float(float) = value {
+            if !float.is_finite() {
+                match not_nan(float.into_inner(), nan_policy) {
+                    Some(replaced) => *value = Value::from(replaced),
+                    None => return false,
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Converts a gauge or counter value, returning `None` if it is NaN or infinite.
+fn scalar_value(value: f64, nan_policy: NewRelicNanPolicy) -> Option<Value> {
+    not_nan(value, nan_policy).map(Value::from)
+}
+
+/// Builds the value object of a New Relic summary metric, returning `None` if any part of it is NaN
+/// or infinite.
+fn summary_value(
+    count: u64,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    nan_policy: NewRelicNanPolicy,
+) -> Option<Value> {
+    let mut summary = BTreeMap::new();
+    summary.insert("count".to_owned(), Value::from(count));
+    summary.insert("sum".to_owned(), Value::from(not_nan(sum, nan_policy)?));
+    if let Some(min) = min {
+        summary.insert("min".to_owned(), Value::from(not_nan(min, nan_policy)?));
+    }
+    if let Some(max) = max {
+        summary.insert("max".to_owned(), Value::from(not_nan(max, nan_policy)?));
+    }
+    Some(Value::from(summary))
+}
+
+/// Collapses the weighted samples of a distribution into a New Relic summary value.
+fn distribution_summary_value(samples: &[Sample], nan_policy: NewRelicNanPolicy) -> Option<Value> {
+    let count = samples.iter().map(|sample| u64::from(sample.rate)).sum();
+    let sum = samples
+        .iter()
+        .map(|sample| sample.value * f64::from(sample.rate))
+        .sum();
+    let observed = || {
+        samples
+            .iter()
+            .filter(|sample| sample.rate > 0)
+            .map(|sample| sample.value)
+    };
+
+    summary_value(
+        count,
+        sum,
+        observed().reduce(f64::min),
+        observed().reduce(f64::max),
+        nan_policy,
+    )
+}
+
+/// Computes the quantiles of a distribution from its weighted samples.
+fn distribution_quantiles(samples: &[Sample], quantiles: &[f64]) -> Vec<Quantile> {
+    if quantiles.is_empty() {
+        return Vec::new();
+    }
+
+    DistributionStatistic::from_samples(samples, quantiles)
+        .map(|statistic| {
+            statistic
+                .quantiles
+                .into_iter()
+                .map(|(quantile, value)| Quantile { quantile, value })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Splits summary quantiles into the minimum and maximum, which are part of a New Relic summary,
+/// and the remaining quantiles, which are not.
+fn split_quantiles(quantiles: Vec<Quantile>) -> (Option<f64>, Option<f64>, Vec<Quantile>) {
+    let mut min = None;
+    let mut max = None;
+    let mut rest = Vec::with_capacity(quantiles.len());
+    for quantile in quantiles {
+        if quantile.quantile <= 0.0 {
+            min = Some(quantile.value);
+        } else if quantile.quantile >= 1.0 {
+            max = Some(quantile.value);
         } else {
-            Err(NewRelicSinkError::new("No valid metrics to generate"))
+            rest.push(quantile);
         }
     }
+    (min, max, rest)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Parses a message containing a JSON object into the fields it holds.
+///
+/// The message may also hold an escaped JSON object, such as `{\"key\": \"value\"}`, which is only
+/// unescaped if the message isn't a JSON object as it is.
+///
+/// Returns `None` if the message isn't a JSON object, and an error if it holds a NaN number. Arrays
+/// and nested objects are flattened into fields whose keys are joined with a `.`, such as `tags.0`.
+fn parse_json_message(message: &Value) -> Result<Option<KeyValData>, FloatIsNan> {
+    let message = message.to_string_lossy();
+    let json_map = serde_json::from_str::<HashMap<String, serde_json::Value>>(&message)
+        .ok()
+        .or_else(|| {
+            // Unescape the message as the contents of a JSON string
+            let unescaped = serde_json::from_str::<String>(&format!("\"{}\"", message)).ok()?;
+            serde_json::from_str(&unescaped).ok()
+        });
+    let Some(json_map) = json_map else {
+        return Ok(None);
+    };
+
+    let mut fields = KeyValData::new();
+    for (k, v) in json_map {
+        insert_json_field(&mut fields, k, v)?;
+    }
+    Ok(Some(fields))
+}
+
+/// Inserts a field parsed from a JSON message, flattening arrays and nested objects.
+fn insert_json_field(
+    fields: &mut KeyValData,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), FloatIsNan> {
+    match value {
+        serde_json::Value::String(s) => {
+            fields.insert(key, Value::from(s));
+        }
+        serde_json::Value::Number(n) => {
+            // Integers are kept as they are, as converting them to floats loses precision. Those
+            // too large for an `i64` can only be sent as floats.
+            if let Some(i) = n.as_i64() {
+                fields.insert(key, Value::from(i));
+            } else if let Some(f) = n.as_f64() {
+                fields.insert(key, Value::from(NotNan::new(f)?));
+            }
+        }
+        serde_json::Value::Bool(b) => {
+            fields.insert(key, Value::from(b));
+        }
+        serde_json::Value::Array(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                insert_json_field(fields, format!("{}.{}", key, index), value)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (field, value) in map {
+                insert_json_field(fields, format!("{}.{}", key, field), value)?;
+            }
+        }
+        serde_json::Value::Null => {}
+    }
+    Ok(())
+}
+
+/// Returns whether the attribute is one of the listed attributes, or is nested in one of them.
+fn matches_attribute(key: &str, attributes: &[String]) -> bool {
+    attributes.iter().any(|attribute| {
+        key.strip_prefix(attribute.as_str())
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// Converts an integer timestamp to milliseconds since the Unix epoch.
+///
+/// Its unit is guessed from its magnitude: timestamps below 10^11 are taken as seconds, which
+/// covers dates until the year 5138, below 10^14 as milliseconds, below 10^17 as microseconds,
+/// and larger ones as nanoseconds.
+fn timestamp_millis_from_integer(timestamp: i64) -> i64 {
+    match timestamp.unsigned_abs() {
+        0..=99_999_999_999 => timestamp.saturating_mul(1_000),
+        100_000_000_000..=99_999_999_999_999 => timestamp,
+        100_000_000_000_000..=99_999_999_999_999_999 => timestamp / 1_000,
+        _ => timestamp / 1_000_000,
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct EventsApiModel(pub Vec<KeyValData>);
 
 impl EventsApiModel {
     pub fn new(events_array: Vec<KeyValData>) -> Self {
         Self(events_array)
     }
-}
 
-impl TryFrom<Vec<Event>> for EventsApiModel {
-    type Error = NewRelicSinkError;
+    pub fn try_from_events(
+        buf_events: Vec<Event>,
+        config: &NewRelicEventsConfig,
+        attributes_config: &NewRelicAttributesConfig,
+    ) -> Result<Self, NewRelicSinkError> {
+        let events_array: Vec<_> =
+            Self::try_data_from_events(buf_events, config, attributes_config)?
+                .into_iter()
+                .map(|(_, event)| event)
+                .collect();
 
-    fn try_from(buf_events: Vec<Event>) -> Result<Self, Self::Error> {
+        if !events_array.is_empty() {
+            Ok(Self::new(events_array))
+        } else {
+            Err(NewRelicSinkError::NoValidEvents)
+        }
+    }
+
+    /// Converts the events into the data of New Relic events, each along with the position of the
+    /// event it comes from.
+    pub fn try_data_from_events(
+        buf_events: Vec<Event>,
+        config: &NewRelicEventsConfig,
+        attributes_config: &NewRelicAttributesConfig,
+    ) -> Result<Vec<(usize, KeyValData)>, NewRelicSinkError> {
         let mut num_non_log_events = 0;
+        let mut num_missing_event_type = 0;
         let mut num_nan_value = 0;
 
-        let events_array: Vec<HashMap<String, Value>> = buf_events
+        let events_array: Vec<_> = buf_events
             .into_iter()
-            .filter_map(|event| {
+            .enumerate()
+            .filter_map(|(index, event)| {
                 let Some(log) = event.try_into_log() else {
                     num_non_log_events += 1;
                     return None;
@@ -172,60 +894,115 @@ impl TryFrom<Vec<Event>> for EventsApiModel {
 
                 let mut event_model = KeyValData::new();
                 for (k, v) in log.convert_to_fields() {
-                    event_model.insert(k, v.clone());
+                    let v = match v {
+                        Value::Timestamp(timestamp) if config.timestamps_as_millis => {
+                            Value::from(timestamp.timestamp_millis())
+                        }
+                        v => v.clone(),
+                    };
+                    event_model.insert(k, v);
                 }
 
                 if let Some(message) = log.get(event_path!("message")) {
-                    let message = message.to_string_lossy().replace("\\\"", "\"");
                     // If message contains a JSON string, parse it and insert all fields into self
-                    if let serde_json::Result::Ok(json_map) =
-                        serde_json::from_str::<HashMap<String, serde_json::Value>>(&message)
-                    {
-                        for (k, v) in json_map {
-                            match v {
-                                serde_json::Value::String(s) => {
-                                    event_model.insert(k, Value::from(s));
+                    match parse_json_message(message) {
+                        Ok(Some(json_fields)) => {
+                            match config.field_precedence {
+                                NewRelicFieldPrecedence::JsonWins => {
+                                    event_model.extend(json_fields)
                                 }
-                                serde_json::Value::Number(n) => {
-                                    if let Some(f) = n.as_f64() {
-                                        event_model.insert(
-                                            k,
-                                            Value::from(NotNan::new(f).ok().or_else(|| {
-                                                num_nan_value += 1;
-                                                None
-                                            })?),
-                                        );
-                                    } else {
-                                        event_model.insert(k, Value::from(n.as_i64()));
+                                NewRelicFieldPrecedence::FieldsWin => {
+                                    for (key, value) in json_fields {
+                                        event_model.entry(key).or_insert(value);
                                     }
                                 }
-                                serde_json::Value::Bool(b) => {
-                                    event_model.insert(k, Value::from(b));
-                                }
-                                _ => {
-                                    // Note that arrays and nested objects are silently dropped.
-                                }
                             }
+                            if !config.keep_raw_message {
+                                event_model.remove("message");
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(_) => {
+                            num_nan_value += 1;
+                            return None;
                         }
-                        event_model.remove("message");
+                    }
+                }
+
+                if !config.include_attributes.is_empty() || !config.exclude_attributes.is_empty() {
+                    event_model.retain(|key, _| {
+                        key == "eventType"
+                            || ((config.include_attributes.is_empty()
+                                || matches_attribute(key, &config.include_attributes))
+                                && !matches_attribute(key, &config.exclude_attributes))
+                    });
+                }
+
+                for (key, value) in default_attributes(attributes_config) {
+                    event_model.entry(key).or_insert(value);
+                }
+                event_model = transform_attributes(
+                    event_model,
+                    &attributes_config.attribute_transformers,
+                    &["eventType", "timestamp"],
+                );
+                for value in event_model.values_mut() {
+                    encode_binary(value, attributes_config.binary_encoding);
+                    truncate_value(value, attributes_config.max_value_bytes);
+                }
+                if !replace_non_finite(event_model.values_mut(), config.nan_policy) {
+                    num_nan_value += 1;
+                    return None;
+                }
+
+                // New Relic expects the timestamp in milliseconds since the Unix epoch
+                let timestamp = match log.get((PathPrefix::Event, &config.timestamp_field)) {
+                    Some(Value::Timestamp(timestamp)) => timestamp.timestamp_millis(),
+                    Some(Value::Integer(timestamp)) => timestamp_millis_from_integer(*timestamp),
+                    _ => Utc::now().timestamp_millis(),
+                };
+                event_model.insert("timestamp".to_owned(), Value::from(timestamp));
+
+                if let Some(event_type_field) = &config.event_type_field {
+                    if let Some(event_type) = log.get((PathPrefix::Event, event_type_field)) {
+                        event_model.insert(
+                            "eventType".to_owned(),
+                            Value::from(event_type.to_string_lossy().into_owned()),
+                        );
                     }
                 }
 
                 if event_model.get("eventType").is_none() {
-                    event_model
-                        .insert("eventType".to_owned(), Value::from("VectorSink".to_owned()));
+                    if config.require_event_type {
+                        num_missing_event_type += 1;
+                        return None;
+                    }
+                    event_model.insert(
+                        "eventType".to_owned(),
+                        Value::from(config.default_event_type.as_str()),
+                    );
                 }
 
-                Some(event_model)
+                Some((index, event_model))
             })
             .collect();
 
+        if num_nan_value > 0 && config.nan_policy == NewRelicNanPolicy::Error {
+            return Err(NewRelicSinkError::NanValue);
+        }
+
         if num_non_log_events > 0 {
             emit!(ComponentEventsDropped::<INTENTIONAL> {
                 count: num_non_log_events,
                 reason: "non-log event"
             });
         }
+        if num_missing_event_type > 0 {
+            emit!(ComponentEventsDropped::<INTENTIONAL> {
+                count: num_missing_event_type,
+                reason: "event missing eventType"
+            });
+        }
         if num_nan_value > 0 {
             emit!(ComponentEventsDropped::<UNINTENTIONAL> {
                 count: num_nan_value,
@@ -233,34 +1010,79 @@ impl TryFrom<Vec<Event>> for EventsApiModel {
             });
         }
 
-        if !events_array.is_empty() {
-            Ok(Self::new(events_array))
-        } else {
-            Err(NewRelicSinkError::new("No valid events to generate"))
-        }
+        Ok(events_array)
+    }
+}
+
+impl TryFrom<Vec<Event>> for EventsApiModel {
+    type Error = NewRelicSinkError;
+
+    fn try_from(buf_events: Vec<Event>) -> Result<Self, Self::Error> {
+        Self::try_from_events(
+            buf_events,
+            &NewRelicEventsConfig::default(),
+            &NewRelicAttributesConfig::default(),
+        )
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The keys of the attributes New Relic reserves for logs, which aren't prefixed.
+const RESERVED_LOG_KEYS: [&str; 6] = [
+    "message",
+    "timestamp",
+    "level",
+    "logtype",
+    "trace.id",
+    "span.id",
+];
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct LogsApiModel(pub Vec<DataStore>);
 
 impl LogsApiModel {
     pub fn new(logs_array: Vec<KeyValData>) -> Self {
+        Self::with_key(LOGS_KEY, logs_array)
+    }
+
+    /// Wraps the logs under `key`, instead of the one New Relic expects.
+    pub fn with_key(key: &str, logs_array: Vec<KeyValData>) -> Self {
         let mut logs_store = DataStore::new();
-        logs_store.insert("logs".to_owned(), logs_array);
+        logs_store.insert(key.to_owned(), logs_array);
         Self(vec![logs_store])
     }
-}
 
-impl TryFrom<Vec<Event>> for LogsApiModel {
-    type Error = NewRelicSinkError;
+    pub fn try_from_events(
+        buf_events: Vec<Event>,
+        config: &NewRelicLogsConfig,
+        attributes_config: &NewRelicAttributesConfig,
+    ) -> Result<Self, NewRelicSinkError> {
+        let logs_array: Vec<_> = Self::try_data_from_events(buf_events, config, attributes_config)?
+            .into_iter()
+            .map(|(_, log)| log)
+            .collect();
 
-    fn try_from(buf_events: Vec<Event>) -> Result<Self, Self::Error> {
+        if !logs_array.is_empty() {
+            Ok(Self::with_key(&config.wrapper_key, logs_array))
+        } else {
+            Err(NewRelicSinkError::NoValidLogs)
+        }
+    }
+
+    /// Converts the events into the data of logs, each along with the position of the event it
+    /// comes from.
+    pub fn try_data_from_events(
+        buf_events: Vec<Event>,
+        config: &NewRelicLogsConfig,
+        attributes_config: &NewRelicAttributesConfig,
+    ) -> Result<Vec<(usize, KeyValData)>, NewRelicSinkError> {
         let mut num_non_log_events = 0;
+        let mut num_missing_message = 0;
+        let mut num_nan_value = 0;
 
-        let logs_array: Vec<HashMap<String, Value>> = buf_events
+        let logs_array: Vec<_> = buf_events
             .into_iter()
-            .filter_map(|event| {
+            .enumerate()
+            .filter_map(|(index, event)| {
                 let Some(log) = event.try_into_log() else {
                     num_non_log_events += 1;
                     return None;
@@ -270,28 +1092,124 @@ impl TryFrom<Vec<Event>> for LogsApiModel {
                 for (k, v) in log.convert_to_fields() {
                     log_model.insert(k, v.clone());
                 }
-                if log.get(event_path!("message")).is_none() {
+                if let Some(message) = log.get(event_path!("message")) {
+                    // If message contains a JSON string, replace it with the fields it holds
+                    match parse_json_message(message) {
+                        Ok(Some(json_fields)) => {
+                            log_model.remove("message");
+                            log_model.extend(json_fields);
+                        }
+                        Ok(None) => {}
+                        Err(_) => {
+                            num_nan_value += 1;
+                            return None;
+                        }
+                    }
+                } else {
+                    if config.drop_missing_message {
+                        num_missing_message += 1;
+                        return None;
+                    }
                     log_model.insert(
                         "message".to_owned(),
-                        Value::from("log from vector".to_owned()),
+                        Value::from(config.placeholder_message.as_str()),
                     );
                 }
 
-                Some(log_model)
+                if let Some(level_field) = &config.level_field {
+                    if let Some(level) = log.get((PathPrefix::Event, level_field)) {
+                        let level = if config.uppercase_level {
+                            Value::from(level.to_string_lossy().to_uppercase())
+                        } else {
+                            level.clone()
+                        };
+                        log_model.remove(&level_field.0.to_string());
+                        log_model.insert("level".to_owned(), level);
+                    }
+                }
+
+                // Use the keys New Relic correlates logs with traces by
+                for (field, key) in [
+                    (&config.trace_id_field, "trace.id"),
+                    (&config.span_id_field, "span.id"),
+                ] {
+                    if let Some(field) = field {
+                        if let Some(id) = log.get((PathPrefix::Event, field)) {
+                            log_model.remove(&field.0.to_string());
+                            log_model.insert(key.to_owned(), id.clone());
+                        }
+                    }
+                }
+
+                for (key, value) in default_attributes(attributes_config) {
+                    log_model.entry(key).or_insert(value);
+                }
+                log_model = transform_attributes(
+                    log_model,
+                    &attributes_config.attribute_transformers,
+                    &RESERVED_LOG_KEYS,
+                );
+                for value in log_model.values_mut() {
+                    encode_binary(value, attributes_config.binary_encoding);
+                    truncate_value(value, attributes_config.max_value_bytes);
+                }
+                if !replace_non_finite(log_model.values_mut(), config.nan_policy) {
+                    num_nan_value += 1;
+                    return None;
+                }
+
+                if let Some(prefix) = &config.attribute_prefix {
+                    log_model = log_model
+                        .into_iter()
+                        .map(|(key, value)| {
+                            if RESERVED_LOG_KEYS.contains(&key.as_str()) {
+                                (key, value)
+                            } else {
+                                (format!("{}{}", prefix, key), value)
+                            }
+                        })
+                        .collect();
+                }
+
+                Some((index, log_model))
             })
             .collect();
 
+        if num_nan_value > 0 && config.nan_policy == NewRelicNanPolicy::Error {
+            return Err(NewRelicSinkError::NanValue);
+        }
+
         if num_non_log_events > 0 {
             emit!(ComponentEventsDropped::<INTENTIONAL> {
                 count: num_non_log_events,
                 reason: "non-log event"
             });
         }
-
-        if !logs_array.is_empty() {
-            Ok(Self::new(logs_array))
-        } else {
-            Err(NewRelicSinkError::new("No valid logs to generate"))
+        if num_missing_message > 0 {
+            emit!(ComponentEventsDropped::<INTENTIONAL> {
+                count: num_missing_message,
+                reason: "log missing message"
+            });
+        }
+        if num_nan_value > 0 {
+            emit!(ComponentEventsDropped::<UNINTENTIONAL> {
+                count: num_nan_value,
+                reason: "NaN value not supported"
+            });
         }
+
+        Ok(logs_array)
+    }
+}
+
+impl TryFrom<Vec<Event>> for LogsApiModel {
+    type Error = NewRelicSinkError;
+
+    fn try_from(buf_events: Vec<Event>) -> Result<Self, Self::Error> {
+        Self::try_from_events(
+            buf_events,
+            &NewRelicLogsConfig::default(),
+            &NewRelicAttributesConfig::default(),
+        )
     }
 }
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, num::NonZeroU32};
+
+use chrono::{DateTime, Duration, Utc};
+use vector_core::event::{metric::MetricSeries, Metric, MetricKind, MetricValue};
+
+use crate::sinks::util::buffer::metrics::{MetricNormalize, MetricSet};
+
+/// Optionally converts absolute counters into incremental ones, which New Relic sends as counts.
+///
+/// The interval of a converted counter is the time elapsed since the previous reading of its
+/// series, unless it already has one.
+///
+/// Series without a reading for longer than the TTL are forgotten, both here and in the state of
+/// the normalizer, so series that stop being reported don't hold memory forever. Their next
+/// reading is then only used as a reference again.
+#[derive(Default)]
+pub struct NewRelicMetricsNormalizer {
+    convert_absolute_counters: bool,
+    ttl: Duration,
+    last_seen: HashMap<MetricSeries, DateTime<Utc>>,
+    last_expired: Option<DateTime<Utc>>,
+}
+
+impl NewRelicMetricsNormalizer {
+    pub fn new(convert_absolute_counters: bool, ttl_secs: u64) -> Self {
+        Self {
+            convert_absolute_counters,
+            ttl: Duration::seconds(
+                i64::try_from(ttl_secs)
+                    .unwrap_or(i64::MAX)
+                    .min(i64::MAX / 1_000),
+            ),
+            last_seen: HashMap::new(),
+            last_expired: None,
+        }
+    }
+
+    /// Removes the series whose last reading is older than the TTL, as of `now`.
+    ///
+    /// This only looks through the series once per TTL, so a series is forgotten at most twice the
+    /// TTL after its last reading.
+    fn expire(&mut self, state: &mut MetricSet, now: DateTime<Utc>) {
+        let last_expired = *self.last_expired.get_or_insert(now);
+        if now - last_expired < self.ttl {
+            return;
+        }
+        self.last_expired = Some(now);
+
+        let ttl = self.ttl;
+        self.last_seen.retain(|series, last_seen| {
+            let live = now - *last_seen <= ttl;
+            if !live {
+                state.remove(series);
+            }
+            live
+        });
+    }
+}
+
+impl MetricNormalize for NewRelicMetricsNormalizer {
+    fn normalize(&mut self, state: &mut MetricSet, metric: Metric) -> Option<Metric> {
+        match (metric.kind(), metric.value()) {
+            (MetricKind::Absolute, MetricValue::Counter { .. })
+                if self.convert_absolute_counters =>
+            {
+                let timestamp = metric.timestamp().unwrap_or_else(Utc::now);
+                self.expire(state, timestamp);
+                let previous = self.last_seen.insert(metric.series().clone(), timestamp);
+
+                // The first reading of a series only serves as the reference for the next one
+                let metric = state.make_incremental(metric)?;
+                if metric.interval_ms().is_some() {
+                    return Some(metric);
+                }
+                let interval_ms = previous
+                    .and_then(|previous| {
+                        u32::try_from((timestamp - previous).num_milliseconds()).ok()
+                    })
+                    .and_then(NonZeroU32::new);
+                Some(metric.with_interval_ms(interval_ms))
+            }
+            _ => Some(metric),
+        }
+    }
+}
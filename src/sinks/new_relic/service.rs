@@ -1,5 +1,6 @@
 use std::{
     fmt::Debug,
+    io::Write,
     sync::Arc,
     task::{Context, Poll},
 };
@@ -7,14 +8,20 @@ use std::{
 use bytes::Bytes;
 use http::{
     header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
-    Request,
+    Request, StatusCode,
 };
 use hyper::Body;
+use tower::retry::Policy;
 use tracing::Instrument;
 
-use super::{NewRelicCredentials, NewRelicSinkError};
+use super::{
+    model_to_json, NewRelicApiModel, NewRelicApiRetry, NewRelicCredentials, NewRelicSinkError,
+};
 use crate::sinks::prelude::*;
-use crate::{http::HttpClient, sinks::util::Compression};
+use crate::{
+    http::HttpClient,
+    sinks::util::{retries::FixedRetryPolicy, Compression, Compressor},
+};
 
 #[derive(Debug, Clone)]
 pub struct NewRelicApiRequest {
@@ -22,6 +29,8 @@ pub struct NewRelicApiRequest {
     pub finalizers: EventFinalizers,
     pub credentials: Arc<NewRelicCredentials>,
     pub payload: Bytes,
+    /// The model the payload was encoded from, which is split further if the payload is too large.
+    pub model: Option<NewRelicApiModel>,
     pub compression: Compression,
 }
 
@@ -43,13 +52,37 @@ impl MetaDescriptive for NewRelicApiRequest {
 
 #[derive(Debug)]
 pub struct NewRelicApiResponse {
-    event_status: EventStatus,
+    status_code: StatusCode,
+    halved: bool,
     metadata: RequestMetadata,
 }
 
+impl NewRelicApiResponse {
+    pub const fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    /// Whether the payload was too large, and split into halves that were sent on their own.
+    ///
+    /// The status is then the one of the first half that failed once its retries were exhausted,
+    /// or of the last one delivered.
+    pub const fn halved(&self) -> bool {
+        self.halved
+    }
+}
+
 impl DriverResponse for NewRelicApiResponse {
     fn event_status(&self) -> EventStatus {
-        self.event_status
+        if self.status_code.is_success() {
+            EventStatus::Delivered
+        } else if self.status_code.is_client_error()
+            || (self.halved
+                && !NewRelicApiRetry::action_for_status(self.status_code).is_retryable())
+        {
+            EventStatus::Rejected
+        } else {
+            EventStatus::Errored
+        }
     }
 
     fn events_sent(&self) -> &GroupedCountByteSize {
@@ -64,6 +97,9 @@ impl DriverResponse for NewRelicApiResponse {
 #[derive(Debug, Clone)]
 pub struct NewRelicApiService {
     pub client: HttpClient,
+    /// The policy halves of a payload that was too large are retried with, as they can't be
+    /// retried along with the whole payload once some of them have been delivered.
+    pub half_retry_policy: FixedRetryPolicy<NewRelicApiRetry>,
 }
 
 impl Service<NewRelicApiRequest> for NewRelicApiService {
@@ -76,35 +112,161 @@ impl Service<NewRelicApiRequest> for NewRelicApiService {
     }
 
     fn call(&mut self, request: NewRelicApiRequest) -> Self::Future {
-        let mut client = self.client.clone();
+        let client = self.client.clone();
+        let half_retry_policy = self.half_retry_policy.clone();
+        let metadata = request.get_metadata().clone();
 
-        let uri = request.credentials.get_uri();
+        Box::pin(async move {
+            let (status_code, halved) = send_halving(
+                client,
+                request.credentials,
+                request.compression,
+                request.payload,
+                request.model,
+                half_retry_policy,
+                false,
+            )
+            .await?;
 
-        let http_request = Request::post(&uri)
-            .header(CONTENT_TYPE, "application/json")
-            .header("Api-Key", request.credentials.license_key.clone());
+            Ok(NewRelicApiResponse {
+                status_code,
+                halved,
+                metadata,
+            })
+        })
+    }
+}
 
-        let http_request = if let Some(ce) = request.compression.content_encoding() {
-            http_request.header(CONTENT_ENCODING, ce)
+/// Sends the payload, and if New Relic responds that it is too large, splits the model it was
+/// encoded from in half and sends each half on its own instead.
+///
+/// Halves keep being split for as long as they are too large, until they hold a single metric,
+/// event or log. Retrying the whole payload would send the halves that were delivered again, so
+/// each half is retried on its own with `retry_policy` instead. The payload of the request itself
+/// is retried by the service it is sent through.
+///
+/// Returns the status of the response, which is the one of the first half that failed once its
+/// retries were exhausted, or of the last one delivered, along with whether the payload was
+/// halved.
+fn send_halving(
+    client: HttpClient,
+    credentials: Arc<NewRelicCredentials>,
+    compression: Compression,
+    payload: Bytes,
+    model: Option<NewRelicApiModel>,
+    retry_policy: FixedRetryPolicy<NewRelicApiRetry>,
+    is_half: bool,
+) -> BoxFuture<'static, Result<(StatusCode, bool), NewRelicSinkError>> {
+    Box::pin(async move {
+        let status_code = if is_half {
+            let policy = retry_policy.clone();
+            send_retrying(client.clone(), &credentials, compression, payload, policy).await?
         } else {
-            http_request
+            send(client.clone(), &credentials, compression, payload).await?
         };
+        if status_code != StatusCode::PAYLOAD_TOO_LARGE {
+            return Ok((status_code, false));
+        }
 
-        let payload_len = request.payload.len();
-        let metadata = request.get_metadata().clone();
-        let http_request = http_request
-            .header(CONTENT_LENGTH, payload_len)
-            .body(Body::from(request.payload))
-            .expect("building HTTP request failed unexpectedly");
+        let halves = match model.map(NewRelicApiModel::halve) {
+            Some(Ok((first, second))) => [first, second],
+            _ => return Ok((status_code, false)),
+        };
 
-        Box::pin(async move {
-            match client.call(http_request).in_current_span().await {
-                Ok(_) => Ok(NewRelicApiResponse {
-                    event_status: EventStatus::Delivered,
-                    metadata,
-                }),
-                Err(_) => Err(NewRelicSinkError::new("HTTP request error")),
+        let mut aggregate_status_code = None;
+        for half in halves {
+            let payload = encode(&half, compression)?;
+            let (half_status_code, _) = send_halving(
+                client.clone(),
+                Arc::clone(&credentials),
+                compression,
+                payload,
+                Some(half),
+                retry_policy.clone(),
+                true,
+            )
+            .await?;
+
+            if !half_status_code.is_success() {
+                warn!(
+                    message = "Part of a payload that was too large failed to be sent.",
+                    status = %half_status_code,
+                    internal_log_rate_limit = true
+                );
             }
-        })
+            aggregate_status_code = match aggregate_status_code {
+                Some(status_code) if !status_code.is_success() => Some(status_code),
+                _ => Some(half_status_code),
+            };
+        }
+
+        Ok((aggregate_status_code.unwrap_or(status_code), true))
+    })
+}
+
+/// Sends the payload, retrying it with `policy` for as long as the status of the response is
+/// retriable.
+///
+/// Payloads that are too large aren't retried, as they are halved instead.
+async fn send_retrying(
+    client: HttpClient,
+    credentials: &NewRelicCredentials,
+    compression: Compression,
+    payload: Bytes,
+    mut policy: FixedRetryPolicy<NewRelicApiRetry>,
+) -> Result<StatusCode, NewRelicSinkError> {
+    loop {
+        let status_code = send(client.clone(), credentials, compression, payload.clone()).await?;
+        if status_code == StatusCode::PAYLOAD_TOO_LARGE {
+            return Ok(status_code);
+        }
+
+        let response = NewRelicApiResponse {
+            status_code,
+            halved: false,
+            metadata: RequestMetadata::default(),
+        };
+        let result: Result<_, &crate::Error> = Ok(&response);
+        match policy.retry(&(), result) {
+            Some(retry) => policy = retry.await,
+            None => return Ok(status_code),
+        }
+    }
+}
+
+async fn send(
+    mut client: HttpClient,
+    credentials: &NewRelicCredentials,
+    compression: Compression,
+    payload: Bytes,
+) -> Result<StatusCode, NewRelicSinkError> {
+    let uri = credentials.get_uri();
+
+    let http_request = Request::post(&uri)
+        .header(CONTENT_TYPE, "application/json")
+        .header("Api-Key", credentials.license_key.clone());
+
+    let http_request = if let Some(ce) = compression.content_encoding() {
+        http_request.header(CONTENT_ENCODING, ce)
+    } else {
+        http_request
+    };
+
+    let payload_len = payload.len();
+    let http_request = http_request
+        .header(CONTENT_LENGTH, payload_len)
+        .body(Body::from(payload))
+        .expect("building HTTP request failed unexpectedly");
+
+    match client.call(http_request).in_current_span().await {
+        Ok(response) => Ok(response.status()),
+        Err(_) => Err(NewRelicSinkError::Request),
     }
 }
+
+fn encode(model: &NewRelicApiModel, compression: Compression) -> Result<Bytes, NewRelicSinkError> {
+    let json = model_to_json(model)?;
+    let mut compressor = Compressor::from(compression);
+    compressor.write_all(&json)?;
+    Ok(compressor.finish()?.freeze())
+}
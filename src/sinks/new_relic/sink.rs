@@ -1,47 +1,57 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, io::Write, sync::Arc};
 
 use async_trait::async_trait;
-use bytes::Bytes;
+use futures::stream;
+use snafu::Snafu;
 
-use super::{NewRelicApiRequest, NewRelicCredentials, NewRelicEncoder};
+use super::{
+    NewRelicApiRequest, NewRelicChunk, NewRelicCredentials, NewRelicEncoder,
+    NewRelicMetricsNormalizer,
+};
 use crate::{
-    http::get_http_scheme_from_uri, internal_events::SinkRequestBuildError, sinks::prelude::*,
+    http::get_http_scheme_from_uri,
+    internal_events::SinkRequestBuildError,
+    sinks::{
+        prelude::*,
+        util::{buffer::metrics::MetricNormalizer, Compressor},
+    },
 };
 
-#[derive(Debug)]
-pub struct NewRelicSinkError {
-    message: String,
+#[derive(Debug, Snafu)]
+pub enum NewRelicSinkError {
+    #[snafu(display("NaN value not supported"))]
+    NanValue,
+    #[snafu(display("No valid metrics to generate"))]
+    NoValidMetrics,
+    #[snafu(display("No valid events to generate"))]
+    NoValidEvents,
+    #[snafu(display("No valid logs to generate"))]
+    NoValidLogs,
+    #[snafu(display("Failed generating JSON: {}", source))]
+    Json { source: serde_json::Error },
+    #[snafu(display("{}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("HTTP request error"))]
+    Request,
+    #[snafu(display("{}", message))]
+    Other { message: String },
 }
 
 impl NewRelicSinkError {
     pub fn new(msg: &str) -> Self {
-        NewRelicSinkError {
+        NewRelicSinkError::Other {
             message: String::from(msg),
         }
     }
 
     pub fn boxed(msg: &str) -> Box<Self> {
-        Box::new(NewRelicSinkError {
-            message: String::from(msg),
-        })
-    }
-}
-
-impl std::fmt::Display for NewRelicSinkError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.message)
-    }
-}
-
-impl std::error::Error for NewRelicSinkError {
-    fn description(&self) -> &str {
-        &self.message
+        Box::new(Self::new(msg))
     }
 }
 
 impl From<std::io::Error> for NewRelicSinkError {
-    fn from(error: std::io::Error) -> Self {
-        Self::new(&error.to_string())
+    fn from(source: std::io::Error) -> Self {
+        Self::Io { source }
     }
 }
 
@@ -55,47 +65,49 @@ struct NewRelicRequestBuilder {
     encoder: NewRelicEncoder,
     compression: Compression,
     credentials: Arc<NewRelicCredentials>,
+    max_payload_bytes: usize,
 }
 
-impl RequestBuilder<Vec<Event>> for NewRelicRequestBuilder {
-    type Metadata = EventFinalizers;
-    type Events = Vec<Event>;
-    type Encoder = NewRelicEncoder;
-    type Payload = Bytes;
-    type Request = NewRelicApiRequest;
-    type Error = NewRelicSinkError;
-
-    fn compression(&self) -> Compression {
-        self.compression
-    }
-
-    fn encoder(&self) -> &Self::Encoder {
-        &self.encoder
-    }
-
-    fn split_input(
+impl NewRelicRequestBuilder {
+    /// Builds the requests sending a batch of events, which is split into several requests to
+    /// respect the payload size limit.
+    ///
+    /// Each request only holds the finalizers of the events it sends.
+    fn build_requests(
         &self,
-        mut input: Vec<Event>,
-    ) -> (Self::Metadata, RequestMetadataBuilder, Self::Events) {
-        let builder = RequestMetadataBuilder::from_events(&input);
-        let finalizers = input.take_finalizers();
-
-        (finalizers, builder, input)
+        events: Vec<Event>,
+    ) -> Vec<Result<NewRelicApiRequest, NewRelicSinkError>> {
+        match self.encoder.encode_chunks(events, self.max_payload_bytes) {
+            Ok(chunks) => chunks
+                .into_iter()
+                .map(|chunk| self.build_request(chunk))
+                .collect(),
+            Err(error) => vec![Err(error)],
+        }
     }
 
-    fn build_request(
-        &self,
-        finalizers: Self::Metadata,
-        metadata: RequestMetadata,
-        payload: EncodeResult<Self::Payload>,
-    ) -> Self::Request {
-        NewRelicApiRequest {
+    fn build_request(&self, chunk: NewRelicChunk) -> Result<NewRelicApiRequest, NewRelicSinkError> {
+        let mut compressor = Compressor::from(self.compression);
+        compressor.write_all(&chunk.json)?;
+        let payload = compressor.finish()?.freeze();
+        let metadata = RequestMetadata::new(
+            chunk.event_count,
+            chunk.events_byte_size,
+            chunk.json.len(),
+            payload.len(),
+            chunk.json_size,
+        );
+
+        Ok(NewRelicApiRequest {
             metadata,
-            finalizers,
+            finalizers: chunk.finalizers,
             credentials: Arc::clone(&self.credentials),
-            payload: payload.into_payload(),
+            payload,
+            // The model is kept along with the payload, for the service to split it if it is too
+            // large
+            model: Some(chunk.model),
             compression: self.compression,
-        }
+        })
     }
 }
 
@@ -104,6 +116,7 @@ pub struct NewRelicSink<S> {
     pub encoder: NewRelicEncoder,
     pub credentials: Arc<NewRelicCredentials>,
     pub compression: Compression,
+    pub max_payload_bytes: usize,
     pub batcher_settings: BatcherSettings,
 }
 
@@ -115,16 +128,34 @@ where
     S::Error: Debug + Into<crate::Error> + Send,
 {
     async fn run_inner(self: Box<Self>, input: BoxStream<'_, Event>) -> Result<(), ()> {
-        let request_builder = NewRelicRequestBuilder {
+        let mut normalizer = MetricNormalizer::from(NewRelicMetricsNormalizer::new(
+            self.encoder.metrics.convert_absolute_counters,
+            self.encoder.metrics.absolute_counters_ttl_secs,
+        ));
+        let request_builder = Arc::new(NewRelicRequestBuilder {
             encoder: self.encoder,
             compression: self.compression,
             credentials: Arc::clone(&self.credentials),
-        };
+            max_payload_bytes: self.max_payload_bytes,
+        });
         let protocol = get_http_scheme_from_uri(&self.credentials.get_uri());
 
         input
+            .filter_map(move |event| {
+                future::ready(if let Event::Metric(metric) = event {
+                    normalizer.normalize(metric).map(Event::Metric)
+                } else {
+                    Some(event)
+                })
+            })
             .batched(self.batcher_settings.as_byte_size_config())
-            .request_builder(default_request_builder_concurrency_limit(), request_builder)
+            // A batch may be split into several requests to respect the payload size limit, which
+            // are built concurrently
+            .concurrent_map(default_request_builder_concurrency_limit(), move |batch| {
+                let request_builder = Arc::clone(&request_builder);
+                Box::pin(async move { request_builder.build_requests(batch) })
+            })
+            .flat_map(stream::iter)
             .filter_map(
                 |request: Result<NewRelicApiRequest, NewRelicSinkError>| async move {
                     match request {
@@ -1,14 +1,37 @@
-use std::{collections::HashMap, convert::TryFrom, num::NonZeroU32, time::SystemTime};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    io::{Read, Write},
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
+use flate2::read::GzDecoder;
 use futures::{future::ready, stream};
 use serde::Deserialize;
-use vector_core::config::{init_telemetry, Tags, Telemetry};
+use tower::Service;
+use vector_core::{
+    config::{init_telemetry, Tags, Telemetry},
+    metric_tags,
+    stream::DriverResponse,
+};
 
 use super::*;
 use crate::{
-    config::{GenerateConfig, SinkConfig, SinkContext},
-    event::{Event, LogEvent, Metric, MetricKind, MetricValue, Value},
+    config::{GenerateConfig, ProxyConfig, SinkConfig, SinkContext},
+    event::{
+        metric::Sample, BatchNotifier, BatchStatus, Event, EventMetadata, EventStatus, Finalizable,
+        LogEvent, Metric, MetricKind, MetricValue, StatisticKind, Value,
+    },
+    http::HttpClient,
+    sinks::util::{
+        buffer::metrics::MetricNormalizer,
+        encoding::Encoder,
+        retries::{FixedRetryPolicy, RetryLogic},
+        Compression, Compressor,
+    },
     test_util::{
         components::{
             run_and_assert_data_volume_sink_compliance, run_and_assert_sink_compliance,
@@ -158,6 +181,306 @@ fn generate_event_api_model() {
     );
 }
 
+#[test]
+fn generate_event_api_model_json_message_nested() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from(
+            "{\"tags\": [\"a\", \"b\"], \"user\": {\"name\": \"Joe\", \"address\": {\"city\": \"Paris\"}}}"
+                .to_owned(),
+        ),
+    );
+    let event = Event::Log(LogEvent::from(map));
+    let model =
+        EventsApiModel::try_from(vec![event]).expect("Failed mapping events into API model");
+
+    assert_eq!(model.0.len(), 1);
+    assert!(model.0[0].get("message").is_none());
+    assert!(model.0[0].get("tags").is_none());
+    assert_eq!(model.0[0].get("tags.0").unwrap(), &Value::from("a"));
+    assert_eq!(model.0[0].get("tags.1").unwrap(), &Value::from("b"));
+    assert!(model.0[0].get("user").is_none());
+    assert_eq!(model.0[0].get("user.name").unwrap(), &Value::from("Joe"));
+    assert_eq!(
+        model.0[0].get("user.address.city").unwrap(),
+        &Value::from("Paris")
+    );
+}
+
+#[test]
+fn generate_event_api_model_json_message_escaped() {
+    let model_of = |message: &str| {
+        let mut map = HashMap::<String, Value>::new();
+        map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+        map.insert("message".to_owned(), Value::from(message.to_owned()));
+        EventsApiModel::try_from(vec![Event::Log(LogEvent::from(map))])
+            .expect("Failed mapping events into API model")
+    };
+
+    // Escaped quotes within a JSON object are kept
+    let model = model_of(r#"{"quote": "say \"hi\""}"#);
+
+    assert!(model.0[0].get("message").is_none());
+    assert_eq!(
+        model.0[0].get("quote").unwrap(),
+        &Value::from(r#"say "hi""#)
+    );
+
+    // An escaped JSON object is unescaped once
+    let model = model_of(r#"{\"my_key\": \"my_value\"}"#);
+
+    assert!(model.0[0].get("message").is_none());
+    assert_eq!(model.0[0].get("my_key").unwrap(), &Value::from("my_value"));
+
+    // Escaped quotes within an escaped JSON object are kept
+    let model = model_of(r#"{\"quote\": \"say \\\"hi\\\"\"}"#);
+
+    assert!(model.0[0].get("message").is_none());
+    assert_eq!(
+        model.0[0].get("quote").unwrap(),
+        &Value::from(r#"say "hi""#)
+    );
+
+    // Messages that aren't JSON objects are left as they are
+    let model = model_of(r#"say \"hi\""#);
+
+    assert_eq!(
+        model.0[0].get("message").unwrap(),
+        &Value::from(r#"say \"hi\""#)
+    );
+}
+
+#[test]
+fn generate_event_api_model_json_message_integers() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from(format!(
+            "{{\"id\": {}, \"ratio\": 0.5, \"huge\": 18446744073709551615}}",
+            i64::MAX
+        )),
+    );
+    let event = Event::Log(LogEvent::from(map));
+    let model =
+        EventsApiModel::try_from(vec![event]).expect("Failed mapping events into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(model.0[0].get("id").unwrap(), &Value::Integer(i64::MAX));
+    assert_eq!(json[0]["id"], serde_json::json!(i64::MAX));
+    assert_eq!(model.0[0].get("ratio").unwrap(), &Value::from(0.5));
+    assert_eq!(
+        model.0[0].get("huge").unwrap(),
+        &Value::from(u64::MAX as f64)
+    );
+}
+
+#[test]
+fn generate_event_api_model_field_precedence() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+    map.insert("user".to_owned(), Value::from("Joe".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from("{\"user\": \"Jane\", \"city\": \"Paris\"}".to_owned()),
+    );
+    let event = Event::Log(LogEvent::from(map));
+
+    // The fields of the JSON object win by default
+    let model = EventsApiModel::try_from(vec![event.clone()])
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("user").unwrap(), &Value::from("Jane"));
+    assert_eq!(model.0[0].get("city").unwrap(), &Value::from("Paris"));
+
+    let config = NewRelicEventsConfig {
+        field_precedence: NewRelicFieldPrecedence::FieldsWin,
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("user").unwrap(), &Value::from("Joe"));
+    assert_eq!(model.0[0].get("city").unwrap(), &Value::from("Paris"));
+}
+
+#[test]
+fn generate_event_api_model_include_attributes() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+    map.insert("user".to_owned(), Value::from("Joe".to_owned()));
+    map.insert("password".to_owned(), Value::from("hunter2".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from("{\"city\": \"Paris\", \"tags\": [\"a\", \"b\"]}".to_owned()),
+    );
+    let event = Event::Log(LogEvent::from(map));
+
+    let config = NewRelicEventsConfig {
+        include_attributes: vec!["user".to_owned(), "tags".to_owned()],
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping events into API model");
+    let mut keys: Vec<_> = model.0[0].keys().map(String::as_str).collect();
+    keys.sort_unstable();
+
+    assert_eq!(
+        keys,
+        vec!["eventType", "tags.0", "tags.1", "timestamp", "user"]
+    );
+}
+
+#[test]
+fn generate_event_api_model_exclude_attributes() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+    map.insert("user".to_owned(), Value::from("Joe".to_owned()));
+    map.insert("password".to_owned(), Value::from("hunter2".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from("{\"city\": \"Paris\", \"tags\": [\"a\", \"b\"]}".to_owned()),
+    );
+    let event = Event::Log(LogEvent::from(map));
+
+    let config = NewRelicEventsConfig {
+        exclude_attributes: vec!["password".to_owned(), "tags".to_owned()],
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping events into API model");
+    let mut keys: Vec<_> = model.0[0].keys().map(String::as_str).collect();
+    keys.sort_unstable();
+
+    assert_eq!(keys, vec!["city", "eventType", "timestamp", "user"]);
+}
+
+#[test]
+fn generate_event_api_model_keep_raw_message() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("eventType".to_owned(), Value::from("TestEvent".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from("{\"my_key\" : \"my_value\"}".to_owned()),
+    );
+    let event = Event::Log(LogEvent::from(map));
+
+    // The message is removed by default
+    let model = EventsApiModel::try_from(vec![event.clone()])
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("my_key").unwrap(), &Value::from("my_value"));
+    assert!(model.0[0].get("message").is_none());
+
+    let config = NewRelicEventsConfig {
+        keep_raw_message: true,
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("my_key").unwrap(), &Value::from("my_value"));
+    assert_eq!(
+        model.0[0].get("message").unwrap(),
+        &Value::from("{\"my_key\" : \"my_value\"}")
+    );
+}
+
+#[test]
+fn generate_event_api_model_default_event_type() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("user".to_owned(), Value::from("Joe".to_owned()));
+    let event = Event::Log(LogEvent::from(map));
+    let model = EventsApiModel::try_from(vec![event.clone()])
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("eventType").unwrap().to_string_lossy(),
+        "VectorSink".to_owned()
+    );
+
+    let config = NewRelicEventsConfig {
+        default_event_type: "MyPipeline".to_owned(),
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("eventType").unwrap().to_string_lossy(),
+        "MyPipeline".to_owned()
+    );
+}
+
+#[test]
+fn generate_event_api_model_event_type_field() {
+    let mut with_kind = LogEvent::default();
+    with_kind.insert("kind", "Deployment");
+    with_kind.insert("user", "Joe");
+    let mut without_kind = LogEvent::default();
+    without_kind.insert("user", "Jane");
+
+    let config = NewRelicEventsConfig {
+        event_type_field: Some("kind".into()),
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(
+        vec![Event::Log(with_kind), Event::Log(without_kind)],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("eventType").unwrap(),
+        &Value::from("Deployment")
+    );
+    // The field is copied, not moved
+    assert_eq!(model.0[0].get("kind").unwrap(), &Value::from("Deployment"));
+    assert_eq!(
+        model.0[1].get("eventType").unwrap(),
+        &Value::from("VectorSink")
+    );
+}
+
+#[test]
+fn generate_event_api_model_require_event_type() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("user".to_owned(), Value::from("Joe".to_owned()));
+    let untyped = Event::Log(LogEvent::from(map.clone()));
+    map.insert("eventType".to_owned(), Value::from("Login".to_owned()));
+    let typed = Event::Log(LogEvent::from(map));
+
+    let config = NewRelicEventsConfig {
+        require_event_type: true,
+        ..Default::default()
+    };
+    let model =
+        EventsApiModel::try_from_events(vec![untyped.clone(), typed], &config, &Default::default())
+            .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0.len(), 1);
+    assert_eq!(
+        model.0[0].get("eventType").unwrap().to_string_lossy(),
+        "Login".to_owned()
+    );
+    assert!(
+        EventsApiModel::try_from_events(vec![untyped.clone()], &config, &Default::default())
+            .is_err()
+    );
+
+    // The default event type is set otherwise
+    let model =
+        EventsApiModel::try_from(vec![untyped]).expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("eventType").unwrap().to_string_lossy(),
+        "VectorSink".to_owned()
+    );
+}
+
 #[test]
 fn generate_log_api_model() {
     // Without message field
@@ -199,6 +522,104 @@ fn generate_log_api_model() {
     );
 }
 
+#[test]
+fn generate_log_api_model_json_message() {
+    // With a JSON encoded inside the message field
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("tag_key".to_owned(), Value::from("tag_value".to_owned()));
+    map.insert(
+        "message".to_owned(),
+        Value::from(
+            "{\"message\": \"login\", \"user\": \"Joe\", \"user_id\": 123456, \"admin\": false}"
+                .to_owned(),
+        ),
+    );
+    let event = Event::Log(LogEvent::from(map));
+    let model = LogsApiModel::try_from(vec![event]).expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs.len(), 1);
+    assert_eq!(
+        logs[0].get("tag_key").unwrap().to_string_lossy(),
+        "tag_value".to_owned()
+    );
+    assert_eq!(
+        logs[0].get("message").unwrap().to_string_lossy(),
+        "login".to_owned()
+    );
+    assert_eq!(
+        logs[0].get("user").unwrap().to_string_lossy(),
+        "Joe".to_owned()
+    );
+    assert_eq!(logs[0].get("user_id").unwrap(), &Value::Integer(123456));
+    assert_eq!(logs[0].get("admin").unwrap(), &Value::from(false));
+
+    // With a message that isn't valid JSON
+    let mut map = HashMap::<String, Value>::new();
+    map.insert(
+        "message".to_owned(),
+        Value::from("{\"user\": \"Joe\"".to_owned()),
+    );
+    let event = Event::Log(LogEvent::from(map));
+    let model = LogsApiModel::try_from(vec![event]).expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs.len(), 1);
+    assert_eq!(
+        logs[0].get("message").unwrap().to_string_lossy(),
+        "{\"user\": \"Joe\"".to_owned()
+    );
+    assert!(logs[0].get("user").is_none());
+}
+
+#[test]
+fn generate_log_api_model_missing_message() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("tag_key".to_owned(), Value::from("tag_value".to_owned()));
+    let event = Event::Log(LogEvent::from(map));
+
+    // Custom placeholder
+    let config = NewRelicLogsConfig {
+        placeholder_message: "(no message)".to_owned(),
+        ..Default::default()
+    };
+    let model = LogsApiModel::try_from_events(vec![event.clone()], &config, &Default::default())
+        .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs.len(), 1);
+    assert_eq!(
+        logs[0].get("message").unwrap().to_string_lossy(),
+        "(no message)".to_owned()
+    );
+
+    // Drop logs without a message
+    let config = NewRelicLogsConfig {
+        drop_missing_message: true,
+        ..Default::default()
+    };
+    let mut map = HashMap::<String, Value>::new();
+    map.insert(
+        "message".to_owned(),
+        Value::from("This is a message".to_owned()),
+    );
+    let with_message = Event::Log(LogEvent::from(map));
+    let model = LogsApiModel::try_from_events(
+        vec![event.clone(), with_message],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs.len(), 1);
+    assert_eq!(
+        logs[0].get("message").unwrap().to_string_lossy(),
+        "This is a message".to_owned()
+    );
+    assert!(LogsApiModel::try_from_events(vec![event], &config, &Default::default()).is_err());
+}
+
 #[test]
 fn generate_metric_api_model() {
     // Without timestamp
@@ -274,3 +695,2361 @@ fn generate_metric_api_model() {
     assert!(metrics[0].get("interval.ms").is_some());
     assert_eq!(metrics[0].get("interval.ms").unwrap(), &Value::from(1000));
 }
+
+#[test]
+fn generate_metric_api_model_value_multiplier() {
+    let m = Metric::new(
+        "my_gauge",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 1.5 },
+    )
+    .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())));
+    let config = NewRelicMetricsConfig {
+        value_multiplier: Some(1000.0),
+        ..Default::default()
+    };
+    let model =
+        MetricsApiModel::try_from_events(vec![Event::Metric(m)], &config, &Default::default())
+            .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics[0].get("value").unwrap(), &Value::from(1500.0));
+}
+
+#[test]
+fn generate_metric_api_model_metric_kind_attribute() {
+    let counter = |kind| {
+        Event::Metric(
+            Metric::new("my_counter", kind, MetricValue::Counter { value: 1.0 })
+                .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())))
+                .with_interval_ms(NonZeroU32::new(1000)),
+        )
+    };
+
+    // The kind isn't sent by default
+    let model = MetricsApiModel::try_from(vec![counter(MetricKind::Absolute)])
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert!(json[0]["metrics"][0].get("attributes").is_none());
+
+    let config = NewRelicMetricsConfig {
+        metric_kind_attribute: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(
+        vec![
+            counter(MetricKind::Incremental),
+            counter(MetricKind::Absolute),
+        ],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+    let metrics = json[0]["metrics"].as_array().unwrap();
+
+    assert_eq!(metrics[0]["type"], "count");
+    assert_eq!(
+        metrics[0]["attributes"],
+        serde_json::json!({"vector.metric_kind": "incremental"})
+    );
+    assert_eq!(metrics[1]["type"], "gauge");
+    assert_eq!(
+        metrics[1]["attributes"],
+        serde_json::json!({"vector.metric_kind": "absolute"})
+    );
+}
+
+#[test]
+fn generate_metric_api_model_omit_gauge_type() {
+    let events = || {
+        vec![
+            Event::Metric(Metric::new(
+                "my_gauge",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: 100.0 },
+            )),
+            Event::Metric(
+                Metric::new(
+                    "my_counter",
+                    MetricKind::Incremental,
+                    MetricValue::Counter { value: 1.0 },
+                )
+                .with_interval_ms(NonZeroU32::new(1000)),
+            ),
+        ]
+    };
+
+    // The type is sent by default
+    let model = MetricsApiModel::try_from(events()).expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["type"], "gauge");
+    assert_eq!(json[0]["metrics"][1]["type"], "count");
+
+    let config = NewRelicMetricsConfig {
+        omit_gauge_type: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(events(), &config, &Default::default())
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert!(json[0]["metrics"][0].get("type").is_none());
+    assert_eq!(json[0]["metrics"][1]["type"], "count");
+}
+
+#[test]
+fn generate_metric_api_model_sample_rate() {
+    let sampled_counter = Event::Metric(
+        Metric::new(
+            "my_counter",
+            MetricKind::Incremental,
+            MetricValue::Counter { value: 5.0 },
+        )
+        .with_tags(Some(metric_tags!("sample_rate" => "0.1", "env" => "prod")))
+        .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())))
+        .with_interval_ms(NonZeroU32::new(1000)),
+    );
+    let model_json = |sample_rate_policy| {
+        let config = NewRelicMetricsConfig {
+            sample_rate_policy,
+            ..Default::default()
+        };
+        let model = MetricsApiModel::try_from_events(
+            vec![sampled_counter.clone()],
+            &config,
+            &Default::default(),
+        )
+        .expect("Failed mapping metrics into API model");
+        serde_json::to_value(&model).expect("Failed serializing API model")
+    };
+
+    // The sample rate is a tag like any other by default
+    let json = model_json(NewRelicSampleRatePolicy::Ignore);
+    assert_eq!(json[0]["metrics"][0]["value"], 5.0);
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"env": "prod", "sample_rate": "0.1"})
+    );
+
+    let json = model_json(NewRelicSampleRatePolicy::Scale);
+    assert_eq!(json[0]["metrics"][0]["value"], 50.0);
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"env": "prod"})
+    );
+
+    let json = model_json(NewRelicSampleRatePolicy::Attribute);
+    assert_eq!(json[0]["metrics"][0]["value"], 5.0);
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"env": "prod", "sampleRate": 0.1})
+    );
+}
+
+#[test]
+fn generate_metric_api_model_distribution() {
+    let m = Metric::new(
+        "my_distribution",
+        MetricKind::Incremental,
+        MetricValue::Distribution {
+            samples: vector_core::samples![1.0 => 1, 2.0 => 2, 5.0 => 3],
+            statistic: StatisticKind::Summary,
+        },
+    )
+    .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())))
+    .with_interval_ms(NonZeroU32::new(1000));
+    let event = Event::Metric(m);
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(
+        metrics[0].get("name").unwrap().to_string_lossy(),
+        "my_distribution".to_owned()
+    );
+    assert_eq!(
+        metrics[0].get("type").unwrap().to_string_lossy(),
+        "summary".to_owned()
+    );
+    assert_eq!(
+        metrics[0].get("value").unwrap(),
+        &Value::from(BTreeMap::from([
+            ("count".to_owned(), Value::from(6)),
+            ("sum".to_owned(), Value::from(20.0)),
+            ("min".to_owned(), Value::from(1.0)),
+            ("max".to_owned(), Value::from(5.0)),
+        ]))
+    );
+    assert!(metrics[0].get("timestamp").is_some());
+    assert_eq!(metrics[0].get("interval.ms").unwrap(), &Value::from(1000));
+}
+
+#[test]
+fn generate_metric_api_model_distribution_quantiles() {
+    let samples = (1..=100)
+        .map(|value| Sample {
+            value: f64::from(value),
+            rate: 1,
+        })
+        .collect();
+    let m = Metric::new(
+        "my_distribution",
+        MetricKind::Incremental,
+        MetricValue::Distribution {
+            samples,
+            statistic: StatisticKind::Summary,
+        },
+    )
+    .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())))
+    .with_interval_ms(NonZeroU32::new(1000));
+    let config = NewRelicMetricsConfig {
+        distribution_quantiles: vec![0.5, 0.95, 0.99],
+        ..Default::default()
+    };
+    let model =
+        MetricsApiModel::try_from_events(vec![Event::Metric(m)], &config, &Default::default())
+            .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+    let metrics = json[0]["metrics"].as_array().unwrap();
+
+    assert_eq!(metrics.len(), 4);
+    assert_eq!(metrics[0]["name"], "my_distribution");
+    assert_eq!(metrics[0]["type"], "summary");
+    assert_eq!(
+        metrics[0]["value"],
+        serde_json::json!({"count": 100, "sum": 5050.0, "min": 1.0, "max": 100.0})
+    );
+
+    for (metric, (name, expected)) in metrics[1..].iter().zip([
+        ("my_distribution.p50", 50.0),
+        ("my_distribution.p95", 95.0),
+        ("my_distribution.p99", 99.0),
+    ]) {
+        assert_eq!(metric["name"], name);
+        assert_eq!(metric["type"], "gauge");
+        assert!((metric["value"].as_f64().unwrap() - expected).abs() <= 1.0);
+        assert!(metric.get("interval.ms").is_none());
+    }
+}
+
+#[test]
+fn generate_metric_api_model_histogram() {
+    let m = Metric::new(
+        "my_histogram",
+        MetricKind::Absolute,
+        MetricValue::AggregatedHistogram {
+            buckets: vector_core::buckets![1.0 => 2, 2.0 => 3],
+            count: 5,
+            sum: 7.5,
+        },
+    )
+    .with_tags(Some(metric_tags!("code" => "200")))
+    .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())));
+    let event = Event::Metric(m);
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+    let metric = &json[0]["metrics"][0];
+
+    assert_eq!(metric["name"], "my_histogram");
+    assert_eq!(metric["type"], "summary");
+    assert_eq!(metric["value"], serde_json::json!({"count": 5, "sum": 7.5}));
+    assert_eq!(metric["attributes"], serde_json::json!({"code": "200"}));
+    assert!(metric["timestamp"].is_i64());
+}
+
+#[test]
+fn generate_metric_api_model_summary() {
+    let m = Metric::new(
+        "my_summary",
+        MetricKind::Incremental,
+        MetricValue::AggregatedSummary {
+            quantiles: vector_core::quantiles![0.0 => 1.0, 0.5 => 3.0, 0.99 => 9.0, 1.0 => 10.0],
+            count: 10,
+            sum: 40.0,
+        },
+    )
+    .with_tags(Some(metric_tags!("code" => "200")))
+    .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())))
+    .with_interval_ms(NonZeroU32::new(1000));
+    let event = Event::Metric(m);
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+    let metrics = json[0]["metrics"].as_array().unwrap();
+
+    assert_eq!(metrics.len(), 3);
+    assert_eq!(metrics[0]["name"], "my_summary");
+    assert_eq!(metrics[0]["type"], "summary");
+    assert_eq!(
+        metrics[0]["value"],
+        serde_json::json!({"count": 10, "sum": 40.0, "min": 1.0, "max": 10.0})
+    );
+    assert_eq!(metrics[0]["interval.ms"], 1000);
+
+    assert_eq!(metrics[1]["name"], "my_summary.p50");
+    assert_eq!(metrics[1]["type"], "gauge");
+    assert_eq!(metrics[1]["value"], 3.0);
+    assert!(metrics[1].get("interval.ms").is_none());
+    assert_eq!(metrics[2]["name"], "my_summary.p99");
+    assert_eq!(metrics[2]["type"], "gauge");
+    assert_eq!(metrics[2]["value"], 9.0);
+
+    for metric in metrics {
+        assert_eq!(metric["attributes"], serde_json::json!({"code": "200"}));
+        assert_eq!(metric["timestamp"], metrics[0]["timestamp"]);
+    }
+}
+
+#[test]
+fn generate_metric_api_model_set() {
+    let m = Metric::new(
+        "my_set",
+        MetricKind::Incremental,
+        MetricValue::Set {
+            values: ["alice", "bob", "carol"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        },
+    )
+    .with_tags(Some(metric_tags!("code" => "200")))
+    .with_timestamp(Some(DateTime::<Utc>::from(SystemTime::now())));
+    let event = Event::Metric(m);
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(
+        metrics[0].get("name").unwrap().to_string_lossy(),
+        "my_set".to_owned()
+    );
+    assert_eq!(
+        metrics[0].get("type").unwrap().to_string_lossy(),
+        "gauge".to_owned()
+    );
+    assert_eq!(metrics[0].get("value").unwrap(), &Value::from(3));
+    assert!(metrics[0].get("attributes").is_some());
+    assert!(metrics[0].get("timestamp").is_some());
+}
+
+#[test]
+fn generate_metric_api_model_skips_invalid_metrics() {
+    let events = [1.0, f64::NAN, 2.0, 3.0]
+        .into_iter()
+        .map(|value| {
+            Event::Metric(Metric::new(
+                "my_metric",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value },
+            ))
+        })
+        .collect::<Vec<_>>();
+    let model = MetricsApiModel::try_from(events).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics.len(), 3);
+    for (metric, value) in metrics.iter().zip([1.0, 2.0, 3.0]) {
+        assert_eq!(metric.get("value").unwrap(), &Value::from(value));
+    }
+
+    // Only invalid metrics fail the whole batch
+    let event = Event::Metric(Metric::new(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: f64::NAN },
+    ));
+    assert!(MetricsApiModel::try_from(vec![event]).is_err());
+}
+
+#[test]
+fn generate_metric_api_model_timestamp_millis() {
+    let m = Metric::new(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+    )
+    .with_timestamp(Utc.timestamp_millis_opt(1_692_000_000_500).single());
+    let event = Event::Metric(m);
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(
+        metrics[0].get("timestamp").unwrap(),
+        &Value::from(1_692_000_000_500_i64)
+    );
+
+    // Without timestamp, the current time is also used in milliseconds
+    let before = Utc::now().timestamp_millis();
+    let event = Event::Metric(Metric::new(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+    ));
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+    let timestamp = metrics[0].get("timestamp").unwrap().as_integer().unwrap();
+
+    assert!(timestamp >= before);
+    assert!(timestamp <= Utc::now().timestamp_millis());
+}
+
+#[test]
+fn generate_metric_api_model_max_future_secs() {
+    let in_an_hour = Utc::now() + chrono::Duration::hours(1);
+    let metric = |timestamp: DateTime<Utc>| {
+        Event::Metric(
+            Metric::new(
+                "my_metric",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: 100.0 },
+            )
+            .with_timestamp(Some(timestamp)),
+        )
+    };
+    let timestamp =
+        |metric: &BTreeMap<String, Value>| metric.get("timestamp").unwrap().as_integer().unwrap();
+    let config = NewRelicMetricsConfig {
+        max_future_secs: Some(600),
+        ..Default::default()
+    };
+
+    let before = Utc::now().timestamp_millis();
+    let model = MetricsApiModel::try_from_events(
+        vec![
+            metric(in_an_hour),
+            metric(Utc::now() + chrono::Duration::minutes(5)),
+        ],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    // Metrics timestamped too far ahead are sent as of now
+    assert!(timestamp(&metrics[0]) >= before);
+    assert!(timestamp(&metrics[0]) <= Utc::now().timestamp_millis());
+    // Within the window, timestamps are kept
+    assert!(timestamp(&metrics[1]) > Utc::now().timestamp_millis());
+
+    // Without a window, any timestamp is kept
+    let model = MetricsApiModel::try_from(vec![metric(in_an_hour)])
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(timestamp(&metrics[0]), in_an_hour.timestamp_millis());
+}
+
+#[test]
+fn generate_event_api_model_timestamps_as_millis() {
+    let mut log = LogEvent::from("This is a message");
+    log.insert(
+        "login.at",
+        Value::Timestamp(Utc.timestamp_millis_opt(1_692_000_000_500).unwrap()),
+    );
+    let event = Event::Log(log);
+
+    // Timestamps are sent as strings by default
+    let model = EventsApiModel::try_from(vec![event.clone()])
+        .expect("Failed mapping events into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["login.at"], "2023-08-14T08:00:00.500Z");
+
+    let config = NewRelicEventsConfig {
+        timestamps_as_millis: true,
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping events into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(
+        model.0[0].get("login.at").unwrap(),
+        &Value::from(1_692_000_000_500_i64)
+    );
+    assert_eq!(json[0]["login.at"], 1_692_000_000_500_i64);
+}
+
+#[test]
+fn generate_metric_api_model_metadata_attributes() {
+    let metadata = EventMetadata::default_with_value(Value::from(BTreeMap::from([
+        (
+            "a".to_owned(),
+            Value::from(BTreeMap::from([
+                ("b".to_owned(), Value::from(1)),
+                (
+                    "c".to_owned(),
+                    Value::from(BTreeMap::from([("d".to_owned(), Value::from(2))])),
+                ),
+            ])),
+        ),
+        (
+            "e".to_owned(),
+            Value::from(vec![Value::from("x"), Value::from("y")]),
+        ),
+    ])));
+    let m = Metric::new_with_metadata(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+        metadata,
+    )
+    .with_tags(Some(metric_tags!("code" => "200")));
+
+    // Metadata is not added by default
+    let model = MetricsApiModel::try_from(vec![Event::Metric(m.clone())])
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"code": "200"})
+    );
+
+    let config = NewRelicMetricsConfig {
+        metadata_attributes: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(
+        vec![Event::Metric(m.clone())],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"a.b": 1, "a.c.d": 2, "e.0": "x", "e.1": "y", "code": "200"})
+    );
+
+    // Custom separator
+    let config = NewRelicMetricsConfig {
+        metadata_attributes: true,
+        flatten_separator: "_".to_owned(),
+        ..Default::default()
+    };
+    let model =
+        MetricsApiModel::try_from_events(vec![Event::Metric(m)], &config, &Default::default())
+            .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"a_b": 1, "a_c_d": 2, "e_0": "x", "e_1": "y", "code": "200"})
+    );
+}
+
+#[test]
+fn generate_metric_api_model_namespace() {
+    let namespaced = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_namespace(Some("my_namespace")),
+    );
+    let plain = Event::Metric(Metric::new(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+    ));
+
+    let config = NewRelicMetricsConfig {
+        prefix_namespace: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(
+        vec![namespaced.clone(), plain.clone()],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(
+        metrics[0].get("name").unwrap().to_string_lossy(),
+        "my_namespace.my_metric".to_owned()
+    );
+    assert_eq!(
+        metrics[1].get("name").unwrap().to_string_lossy(),
+        "my_metric".to_owned()
+    );
+
+    // By default, names are sent without the namespace
+    let model = MetricsApiModel::try_from(vec![namespaced, plain])
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(
+        metrics[0].get("name").unwrap().to_string_lossy(),
+        "my_metric".to_owned()
+    );
+    assert_eq!(
+        metrics[1].get("name").unwrap().to_string_lossy(),
+        "my_metric".to_owned()
+    );
+}
+
+#[test]
+fn generate_metric_api_model_sanitize_names() {
+    let metric = |name: &str| {
+        Event::Metric(Metric::new(
+            name,
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        ))
+    };
+    let config = NewRelicMetricsConfig {
+        sanitize_names: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(
+        vec![metric("disk usage (%)/sda1"), metric(&"a".repeat(300))],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["name"], "disk_usage_____sda1");
+    assert_eq!(json[0]["metrics"][1]["name"], "a".repeat(255));
+
+    // Names are sent as they are by default
+    let model = MetricsApiModel::try_from(vec![metric("disk usage (%)/sda1")])
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["name"], "disk usage (%)/sda1");
+}
+
+#[test]
+fn generate_metric_api_model_nan_policy() {
+    let events = || {
+        vec![
+            Event::Metric(Metric::new(
+                "nan_gauge",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: f64::NAN },
+            )),
+            Event::Metric(Metric::new(
+                "my_gauge",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: 100.0 },
+            )),
+        ]
+    };
+
+    // Dropping the metric is the default
+    let model = MetricsApiModel::try_from(events()).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(
+        metrics[0].get("name").unwrap().to_string_lossy(),
+        "my_gauge".to_owned()
+    );
+
+    let config = NewRelicMetricsConfig {
+        nan_policy: NewRelicNanPolicy::Zero,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(events(), &config, &Default::default())
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics.len(), 2);
+    assert_eq!(
+        metrics[0].get("name").unwrap().to_string_lossy(),
+        "nan_gauge".to_owned()
+    );
+    assert_eq!(metrics[0].get("value").unwrap(), &Value::from(0.0));
+
+    let config = NewRelicMetricsConfig {
+        nan_policy: NewRelicNanPolicy::Error,
+        ..Default::default()
+    };
+    assert!(matches!(
+        MetricsApiModel::try_from_events(events(), &config, &Default::default()),
+        Err(NewRelicSinkError::NanValue)
+    ));
+}
+
+/// Sends a NaN gauge and a valid one through the sink, returning the number of requests sent.
+async fn send_nan_gauge(nan_policy: NewRelicNanPolicy) -> usize {
+    let requests = Arc::new(Mutex::new(0));
+    let handler_requests = Arc::clone(&requests);
+    let endpoint = spawn_blackhole_http_server(move |request| {
+        *handler_requests.lock().unwrap() += 1;
+        always_200_response(request)
+    })
+    .await;
+
+    let config = NewRelicConfig {
+        license_key: "xxxx".to_owned().into(),
+        account_id: "xxxx".to_owned().into(),
+        api: NewRelicApi::Metrics,
+        metrics: NewRelicMetricsConfig {
+            nan_policy,
+            ..Default::default()
+        },
+        override_uri: Some(endpoint),
+        ..Default::default()
+    };
+    let (sink, _healthcheck) = config.build(SinkContext::default()).await.unwrap();
+    sink.run_events(vec![
+        Event::Metric(Metric::new(
+            "nan_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: f64::NAN },
+        )),
+        Event::Metric(Metric::new(
+            "my_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )),
+    ])
+    .await
+    .unwrap();
+
+    let requests = *requests.lock().unwrap();
+    requests
+}
+
+#[tokio::test]
+async fn nan_policy_batch() {
+    // Only the NaN metric is dropped, so the valid one is still sent
+    assert_eq!(send_nan_gauge(NewRelicNanPolicy::Drop).await, 1);
+
+    // The whole batch fails, so the valid metric isn't sent either
+    assert_eq!(send_nan_gauge(NewRelicNanPolicy::Error).await, 0);
+}
+
+#[test]
+fn nan_policy_error_rejects_batch() {
+    crate::metrics::init_test();
+    let discarded_events = || {
+        crate::metrics::Controller::get()
+            .expect("There must be a controller")
+            .capture_metrics()
+            .into_iter()
+            .filter(|metric| {
+                metric.name() == "component_discarded_events_total"
+                    && metric.tag_value("intentional").as_deref() == Some("false")
+            })
+            .map(|metric| match metric.value() {
+                MetricValue::Counter { value } => *value,
+                _ => 0.0,
+            })
+            .sum::<f64>()
+    };
+    let before = discarded_events();
+
+    let mut encoder = encoder(NewRelicApi::Metrics);
+    encoder.metrics.nan_policy = NewRelicNanPolicy::Error;
+    let (batch, mut receiver) = BatchNotifier::new_with_receiver();
+    let events = vec![
+        Event::Metric(Metric::new(
+            "nan_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: f64::NAN },
+        ))
+        .with_batch_notifier(&batch),
+        Event::Metric(Metric::new(
+            "my_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        ))
+        .with_batch_notifier(&batch),
+    ];
+    drop(batch);
+
+    assert!(matches!(
+        encoder.encode_chunks(events, 1_000),
+        Err(NewRelicSinkError::NanValue)
+    ));
+    // The valid metric is rejected and dropped along with the NaN one
+    assert_eq!(receiver.try_recv(), Ok(BatchStatus::Rejected));
+    assert_eq!(discarded_events(), before + 2.0);
+}
+
+#[test]
+fn generate_api_models_infinite_values() {
+    // Infinite metric values are handled as NaN ones
+    let metrics = || {
+        vec![
+            Event::Metric(Metric::new(
+                "inf_gauge",
+                MetricKind::Absolute,
+                MetricValue::Gauge {
+                    value: f64::INFINITY,
+                },
+            )),
+            Event::Metric(Metric::new(
+                "my_gauge",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: 100.0 },
+            )),
+        ]
+    };
+    let model =
+        MetricsApiModel::try_from(metrics()).expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"].as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["metrics"][0]["name"], "my_gauge");
+
+    let config = NewRelicMetricsConfig {
+        nan_policy: NewRelicNanPolicy::Zero,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(metrics(), &config, &Default::default())
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["value"], 0.0);
+
+    let logs = || {
+        let mut log = LogEvent::from("Infinite");
+        log.insert("ratio", f64::NEG_INFINITY);
+        vec![Event::Log(log), Event::Log(LogEvent::from("Finite"))]
+    };
+
+    // Events and logs holding infinite values are dropped by default
+    let model = EventsApiModel::try_from(logs()).expect("Failed mapping events into API model");
+    assert_eq!(model.0.len(), 1);
+    assert_eq!(model.0[0].get("message").unwrap(), &Value::from("Finite"));
+
+    let model = LogsApiModel::try_from(logs()).expect("Failed mapping logs into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+    assert_eq!(json[0]["logs"].as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["logs"][0]["message"], "Finite");
+
+    // Or the values replaced with zero
+    let config = NewRelicEventsConfig {
+        nan_policy: NewRelicNanPolicy::Zero,
+        ..Default::default()
+    };
+    let model = EventsApiModel::try_from_events(logs(), &config, &Default::default())
+        .expect("Failed mapping events into API model");
+    assert_eq!(model.0[0].get("ratio").unwrap(), &Value::from(0.0));
+
+    let config = NewRelicLogsConfig {
+        nan_policy: NewRelicNanPolicy::Zero,
+        ..Default::default()
+    };
+    let model = LogsApiModel::try_from_events(logs(), &config, &Default::default())
+        .expect("Failed mapping logs into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+    assert_eq!(json[0]["logs"][0]["ratio"], 0.0);
+
+    // Or the whole batch failed
+    let config = NewRelicEventsConfig {
+        nan_policy: NewRelicNanPolicy::Error,
+        ..Default::default()
+    };
+    assert!(matches!(
+        EventsApiModel::try_from_events(logs(), &config, &Default::default()),
+        Err(NewRelicSinkError::NanValue)
+    ));
+
+    let config = NewRelicLogsConfig {
+        nan_policy: NewRelicNanPolicy::Error,
+        ..Default::default()
+    };
+    assert!(matches!(
+        LogsApiModel::try_from_events(logs(), &config, &Default::default()),
+        Err(NewRelicSinkError::NanValue)
+    ));
+}
+
+#[test]
+fn generate_api_model_errors() {
+    let log = || Event::Log(LogEvent::from("This is a message"));
+    let metric = || {
+        Event::Metric(Metric::new(
+            "my_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 1.0 },
+        ))
+    };
+
+    assert!(matches!(
+        MetricsApiModel::try_from(vec![log()]),
+        Err(NewRelicSinkError::NoValidMetrics)
+    ));
+    assert!(matches!(
+        EventsApiModel::try_from(vec![metric()]),
+        Err(NewRelicSinkError::NoValidEvents)
+    ));
+    assert!(matches!(
+        LogsApiModel::try_from(vec![metric()]),
+        Err(NewRelicSinkError::NoValidLogs)
+    ));
+    assert_eq!(
+        LogsApiModel::try_from(Vec::new()).unwrap_err().to_string(),
+        "No valid logs to generate"
+    );
+}
+
+#[test]
+fn encode_gzip_round_trip() {
+    let mut map = HashMap::<String, Value>::new();
+    map.insert("user".to_owned(), Value::from("Joe".to_owned()));
+    map.insert("message".to_owned(), Value::from("This is a message"));
+    let event = Event::Log(LogEvent::from(map));
+
+    let encoder = NewRelicEncoder {
+        transformer: Default::default(),
+        credentials: Arc::new(NewRelicCredentials {
+            license_key: "xxxx".to_owned(),
+            account_id: "xxxx".to_owned(),
+            api: NewRelicApi::Logs,
+            region: NewRelicRegion::Us,
+            override_uri: None,
+        }),
+        attributes: Default::default(),
+        metrics: Default::default(),
+        events: Default::default(),
+        logs: Default::default(),
+    };
+    let mut compressor = Compressor::from(Compression::gzip_default());
+    encoder
+        .encode_input(vec![event.clone()], &mut compressor)
+        .expect("Failed encoding logs");
+    let payload = compressor.finish().expect("Failed compressing logs");
+
+    let mut json = Vec::new();
+    GzDecoder::new(payload.as_ref())
+        .read_to_end(&mut json)
+        .expect("Payload is not valid gzip");
+
+    let model = LogsApiModel::try_from(vec![event]).expect("Failed mapping logs into API model");
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&json).unwrap(),
+        serde_json::to_value(&model).unwrap()
+    );
+}
+
+#[test]
+fn encode_gzip_compression_levels() {
+    let events: Vec<_> = (0..1_000)
+        .map(|i| {
+            Event::Log(LogEvent::from(format!(
+                "User {} logged in from 10.0.{}.{} after {} attempts",
+                i,
+                i % 256,
+                i * 7 % 256,
+                i % 5
+            )))
+        })
+        .collect();
+    let encoder = NewRelicEncoder {
+        transformer: Default::default(),
+        credentials: Arc::new(NewRelicCredentials {
+            license_key: "xxxx".to_owned(),
+            account_id: "xxxx".to_owned(),
+            api: NewRelicApi::Logs,
+            region: NewRelicRegion::Us,
+            override_uri: None,
+        }),
+        attributes: Default::default(),
+        metrics: Default::default(),
+        events: Default::default(),
+        logs: Default::default(),
+    };
+    let mut json = Vec::new();
+    encoder
+        .encode_input(events, &mut json)
+        .expect("Failed encoding logs");
+
+    let compress = |level: u32| {
+        let config = indoc::formatdoc! {r#"
+            license_key = "xxxx"
+            account_id = "xxxx"
+            compression = {{ algorithm = "gzip", level = {} }}
+        "#, level};
+        let config = NewRelicConfig::deserialize(toml::de::ValueDeserializer::new(&config))
+            .expect("config should be valid");
+        let mut compressor = Compressor::from(config.compression);
+        compressor
+            .write_all(&json)
+            .expect("Failed compressing logs");
+        let payload = compressor.finish().expect("Failed compressing logs");
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(payload.as_ref())
+            .read_to_end(&mut decompressed)
+            .expect("Payload is not valid gzip");
+        assert_eq!(decompressed, json);
+
+        payload.len()
+    };
+
+    assert!(compress(9) < compress(1));
+}
+
+#[test]
+fn generate_api_model_wrapper_key() {
+    let model = MetricsApiModel::with_key("data", Vec::new());
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json, serde_json::json!([{"data": []}]));
+
+    let config = NewRelicLogsConfig {
+        wrapper_key: "data".to_owned(),
+        ..Default::default()
+    };
+    let events = (0..3)
+        .map(|i| Event::Log(LogEvent::from(format!("This is message {}", i))))
+        .collect();
+    let model = LogsApiModel::try_from_events(events, &config, &Default::default())
+        .expect("Failed mapping logs into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["data"].as_array().unwrap().len(), 3);
+    assert!(json[0].get("logs").is_none());
+
+    // Halving the model keeps the key
+    let (first, second) = NewRelicApiModel::Logs(model)
+        .halve()
+        .expect("Model holds several logs");
+    for model in [first, second] {
+        let NewRelicApiModel::Logs(model) = model else {
+            panic!("Expected a logs model");
+        };
+        let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+        assert!(!json[0]["data"].as_array().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn serialize_api_models_deterministically() {
+    let metrics = || {
+        (0..10)
+            .map(|i| {
+                Event::Metric(
+                    Metric::new(
+                        format!("metric_{}", i),
+                        MetricKind::Absolute,
+                        MetricValue::Gauge { value: i as f64 },
+                    )
+                    .with_tags(Some(metric_tags!(
+                        "zone" => "eu-west-1a",
+                        "env" => "prod",
+                        "host" => "host-1",
+                        "app" => "vector",
+                    )))
+                    .with_timestamp(Utc.timestamp_millis_opt(1_692_000_000_500).single()),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let logs = || {
+        (0..10)
+            .map(|i| {
+                let mut log = LogEvent::default();
+                log.insert("message", format!("message {}", i));
+                log.insert("zone", "eu-west-1a");
+                log.insert("env", "prod");
+                log.insert("app", "vector");
+                Event::Log(log)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let json = serde_json::to_string(&MetricsApiModel::try_from(metrics()).unwrap()).unwrap();
+    for _ in 0..10 {
+        let other = MetricsApiModel::try_from(metrics()).unwrap();
+        assert_eq!(serde_json::to_string(&other).unwrap(), json);
+    }
+    assert!(json.starts_with(r#"[{"metrics":[{"attributes":{"app":"vector","env":"prod""#));
+    let round_trip: MetricsApiModel = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&round_trip).unwrap(), json);
+
+    let json = serde_json::to_string(&LogsApiModel::try_from(logs()).unwrap()).unwrap();
+    for _ in 0..10 {
+        let other = LogsApiModel::try_from(logs()).unwrap();
+        assert_eq!(serde_json::to_string(&other).unwrap(), json);
+    }
+    assert!(json.starts_with(
+        r#"[{"logs":[{"app":"vector","env":"prod","message":"message 0","zone":"eu-west-1a"}"#
+    ));
+    let round_trip: LogsApiModel = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&round_trip).unwrap(), json);
+}
+
+fn encoder(api: NewRelicApi) -> NewRelicEncoder {
+    NewRelicEncoder {
+        transformer: Default::default(),
+        credentials: Arc::new(NewRelicCredentials {
+            license_key: "xxxx".to_owned(),
+            account_id: "xxxx".to_owned(),
+            api,
+            region: NewRelicRegion::Us,
+            override_uri: None,
+        }),
+        attributes: Default::default(),
+        metrics: Default::default(),
+        events: Default::default(),
+        logs: Default::default(),
+    }
+}
+
+#[test]
+fn split_event_batch() {
+    let encoder = encoder(NewRelicApi::Logs);
+    let events: Vec<_> = (0..100)
+        .map(|i| {
+            let mut map = HashMap::<String, Value>::new();
+            map.insert("message".to_owned(), Value::from(format!("{:0>100}", i)));
+            Event::Log(LogEvent::from(map))
+        })
+        .collect();
+
+    let chunks = encoder
+        .encode_chunks(events, 1_000)
+        .expect("Failed encoding logs");
+
+    assert!(chunks.len() > 1);
+    let mut num_logs = 0;
+    for chunk in &chunks {
+        assert!(chunk.json.len() <= 1_000);
+        assert_eq!(chunk.json, model_to_json(&chunk.model).unwrap());
+        num_logs += chunk.event_count;
+    }
+    assert_eq!(num_logs, 100);
+    let NewRelicApiModel::Logs(model) = &chunks[1].model else {
+        panic!("Expected a logs model");
+    };
+    assert_eq!(
+        model.0[0]["logs"][0]["message"],
+        Value::from(format!("{:0>100}", chunks[0].event_count))
+    );
+
+    // An event larger than the limit is sent on its own
+    let events = vec![
+        Event::Log(LogEvent::from("a".repeat(2_000))),
+        Event::Log(LogEvent::from("b")),
+    ];
+    let chunks = encoder
+        .encode_chunks(events, 1_000)
+        .expect("Failed encoding logs");
+
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].event_count, 1);
+    assert!(chunks[0].json.len() > 1_000);
+
+    // Each chunk keeps the finalizers of its own events
+    let (first_batch, mut first_receiver) = BatchNotifier::new_with_receiver();
+    let (second_batch, mut second_receiver) = BatchNotifier::new_with_receiver();
+    let events = vec![
+        Event::Log(LogEvent::from("a".repeat(600))).with_batch_notifier(&first_batch),
+        Event::Log(LogEvent::from("b".repeat(600))).with_batch_notifier(&second_batch),
+    ];
+    drop((first_batch, second_batch));
+    let mut chunks = encoder
+        .encode_chunks(events, 1_000)
+        .expect("Failed encoding logs");
+
+    assert_eq!(chunks.len(), 2);
+    chunks
+        .remove(0)
+        .finalizers
+        .update_status(EventStatus::Delivered);
+    assert_eq!(first_receiver.try_recv(), Ok(BatchStatus::Delivered));
+    assert!(second_receiver.try_recv().is_err());
+    chunks
+        .remove(0)
+        .finalizers
+        .update_status(EventStatus::Rejected);
+    assert_eq!(second_receiver.try_recv(), Ok(BatchStatus::Rejected));
+}
+
+#[tokio::test]
+async fn split_metric_batch_max_payload_bytes() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let handler_received = Arc::clone(&received);
+    let endpoint = spawn_blackhole_http_server(move |request: http::Request<hyper::Body>| {
+        let received = Arc::clone(&handler_received);
+        async move {
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            received.lock().unwrap().push(body);
+            Ok(http::Response::new(hyper::Body::empty()))
+        }
+    })
+    .await;
+
+    let config = NewRelicConfig {
+        license_key: "xxxx".to_owned().into(),
+        account_id: "xxxx".to_owned().into(),
+        api: NewRelicApi::Metrics,
+        compression: Compression::None,
+        max_payload_bytes: 2_000,
+        attributes: NewRelicAttributesConfig {
+            default_attributes: BTreeMap::from([(
+                "service.name".to_owned(),
+                "my-service".repeat(10),
+            )]),
+            ..Default::default()
+        },
+        override_uri: Some(endpoint),
+        ..Default::default()
+    };
+    let (sink, _healthcheck) = config.build(SinkContext::default()).await.unwrap();
+    // Each summary is sent along with the gauges of its quantiles, all of them with the attributes
+    let events = (0..50).map(|i| {
+        Event::Metric(
+            Metric::new(
+                format!("my_summary_{}", i),
+                MetricKind::Absolute,
+                MetricValue::AggregatedSummary {
+                    quantiles: vector_core::quantiles![0.5 => 1.0, 0.9 => 2.0, 0.99 => 3.0],
+                    count: 10,
+                    sum: 20.0,
+                },
+            )
+            .with_tags(Some(
+                metric_tags!("host" => "host-1", "region" => "eu-west-1"),
+            )),
+        )
+    });
+    sink.run_events(events).await.unwrap();
+
+    let received = received.lock().unwrap();
+    assert!(received.len() > 1);
+    let mut num_metrics = 0;
+    for body in received.iter() {
+        assert!(body.len() <= 2_000);
+        let model: MetricsApiModel = serde_json::from_slice(body).unwrap();
+        num_metrics += model.0[0]["metrics"].len();
+    }
+    assert_eq!(num_metrics, 50 * 4);
+}
+
+#[test]
+fn generate_metric_api_model_gauge_interval() {
+    let event = Event::Metric(
+        Metric::new(
+            "my_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_interval_ms(NonZeroU32::new(1000)),
+    );
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(
+        metrics[0].get("type").unwrap().to_string_lossy(),
+        "gauge".to_owned()
+    );
+    assert_eq!(metrics[0].get("interval.ms").unwrap(), &Value::from(1000));
+}
+
+#[test]
+fn generate_metric_api_model_reserved_attribute_keys() {
+    let event = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_timestamp(Some(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()))
+        .with_tags(Some(
+            metric_tags!("timestamp" => "yesterday", "host" => "localhost"),
+        )),
+    );
+    let config = NewRelicMetricsConfig {
+        sanitize_attribute_keys: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(vec![event.clone()], &config, &Default::default())
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(
+        metrics[0].get("timestamp").unwrap(),
+        &Value::from(1_672_531_200_000_i64)
+    );
+    let attributes = metrics[0]
+        .get("attributes")
+        .and_then(Value::as_object)
+        .expect("Attributes not present");
+    assert_eq!(
+        attributes.get("tag.timestamp").unwrap(),
+        &Value::from("yesterday")
+    );
+    assert!(attributes.get("timestamp").is_none());
+    assert_eq!(attributes.get("host").unwrap(), &Value::from("localhost"));
+
+    // By default, reserved keys are kept as they are
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+    let attributes = metrics[0]
+        .get("attributes")
+        .and_then(Value::as_object)
+        .expect("Attributes not present");
+    assert_eq!(
+        attributes.get("timestamp").unwrap(),
+        &Value::from("yesterday")
+    );
+}
+
+#[test]
+fn generate_metric_api_model_multi_value_tags() {
+    let event = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_tags(Some(
+            metric_tags!("code" => "200", "code" => "201", "host" => "localhost"),
+        )),
+    );
+    let model =
+        MetricsApiModel::try_from(vec![event]).expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+    let attributes = metrics[0]
+        .get("attributes")
+        .and_then(Value::as_object)
+        .expect("Attributes not present");
+
+    assert_eq!(
+        attributes.get("code").unwrap(),
+        &Value::Array(vec![Value::from("200"), Value::from("201")])
+    );
+    assert_eq!(attributes.get("host").unwrap(), &Value::from("localhost"));
+}
+
+#[test]
+fn generate_metric_api_model_max_attributes() {
+    let event = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_tags(Some(metric_tags!(
+            "d" => "4",
+            "b" => "2",
+            "e" => "5",
+            "a" => "1",
+            "c" => "3",
+        ))),
+    );
+    let config = NewRelicMetricsConfig {
+        max_attributes: Some(3),
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+    let attributes = metrics[0]
+        .get("attributes")
+        .and_then(Value::as_object)
+        .expect("Attributes not present");
+
+    assert_eq!(
+        attributes.keys().map(String::as_str).collect::<Vec<_>>(),
+        vec!["a", "b", "c"]
+    );
+}
+
+#[test]
+fn generate_api_models_default_host() {
+    let attributes = NewRelicAttributesConfig {
+        default_host: Some("my-host".to_owned()),
+        ..Default::default()
+    };
+
+    // Metrics without a host tag get the default host, while others keep theirs
+    let events = vec![
+        Event::Metric(Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )),
+        Event::Metric(
+            Metric::new(
+                "my_metric",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: 100.0 },
+            )
+            .with_tags(Some(metric_tags!("host" => "localhost"))),
+        ),
+    ];
+    let model = MetricsApiModel::try_from_events(events, &Default::default(), &attributes)
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    let host = |metric: &BTreeMap<String, Value>| {
+        metric
+            .get("attributes")
+            .and_then(Value::as_object)
+            .and_then(|attributes| attributes.get("host"))
+            .cloned()
+    };
+    assert_eq!(host(&metrics[0]), Some(Value::from("my-host")));
+    assert_eq!(host(&metrics[1]), Some(Value::from("localhost")));
+
+    // Logs and events get it too
+    let event = Event::Log(LogEvent::from("This is a message"));
+    let model =
+        LogsApiModel::try_from_events(vec![event.clone()], &Default::default(), &attributes)
+            .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("host").unwrap(), &Value::from("my-host"));
+
+    let model = EventsApiModel::try_from_events(vec![event], &Default::default(), &attributes)
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("host").unwrap(), &Value::from("my-host"));
+}
+
+#[test]
+fn generate_metric_api_model_unit() {
+    let metadata = EventMetadata::default_with_value(Value::from(BTreeMap::from([(
+        "unit".to_owned(),
+        Value::from("seconds"),
+    )])));
+    let with_unit = Event::Metric(Metric::new_with_metadata(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+        metadata,
+    ));
+    let without_unit = Event::Metric(Metric::new(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+    ));
+    let unit = |metric: &BTreeMap<String, Value>| {
+        metric
+            .get("attributes")
+            .and_then(Value::as_object)
+            .and_then(|attributes| attributes.get("unit"))
+            .cloned()
+    };
+
+    // Without a fallback, only metrics with a unit in their metadata get one
+    let model = MetricsApiModel::try_from(vec![with_unit.clone(), without_unit.clone()])
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(unit(&metrics[0]), Some(Value::from("seconds")));
+    assert_eq!(unit(&metrics[1]), None);
+
+    let config = NewRelicMetricsConfig {
+        default_unit: Some("bytes".to_owned()),
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(
+        vec![with_unit, without_unit],
+        &config,
+        &Default::default(),
+    )
+    .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(unit(&metrics[0]), Some(Value::from("seconds")));
+    assert_eq!(unit(&metrics[1]), Some(Value::from("bytes")));
+}
+
+#[test]
+fn generate_metric_api_model_counts_dropped_metrics() {
+    crate::metrics::init_test();
+    let discarded_events = || {
+        crate::metrics::Controller::get()
+            .expect("There must be a controller")
+            .capture_metrics()
+            .into_iter()
+            .filter(|metric| {
+                metric.name() == "component_discarded_events_total"
+                    && metric.tag_value("intentional").as_deref() == Some("false")
+            })
+            .map(|metric| match metric.value() {
+                MetricValue::Counter { value } => *value,
+                _ => 0.0,
+            })
+            .sum::<f64>()
+    };
+    let before = discarded_events();
+
+    // Incremental counter without an interval
+    let events = vec![
+        Event::Metric(Metric::new(
+            "my_counter",
+            MetricKind::Incremental,
+            MetricValue::Counter { value: 100.0 },
+        )),
+        Event::Metric(Metric::new(
+            "my_gauge",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )),
+    ];
+    MetricsApiModel::try_from(events).expect("Failed mapping metrics into API model");
+
+    assert_eq!(discarded_events(), before + 1.0);
+}
+
+#[test]
+fn generate_metric_api_model_missing_interval_policy() {
+    let counter = || {
+        Event::Metric(Metric::new(
+            "my_counter",
+            MetricKind::Incremental,
+            MetricValue::Counter { value: 100.0 },
+        ))
+    };
+    let model_with = |missing_interval_policy| {
+        let config = NewRelicMetricsConfig {
+            missing_interval_policy,
+            synthetic_interval_secs: 10,
+            ..Default::default()
+        };
+        MetricsApiModel::try_from_events(vec![counter()], &config, &Default::default())
+    };
+
+    // Dropped by default
+    assert!(matches!(
+        model_with(NewRelicMissingIntervalPolicy::Drop),
+        Err(NewRelicSinkError::NoValidMetrics)
+    ));
+
+    let model = model_with(NewRelicMissingIntervalPolicy::AsGauge)
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["type"], "gauge");
+    assert_eq!(json[0]["metrics"][0]["value"], 100.0);
+    assert!(json[0]["metrics"][0].get("interval.ms").is_none());
+
+    let model = model_with(NewRelicMissingIntervalPolicy::SynthesizeInterval)
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["type"], "count");
+    assert_eq!(json[0]["metrics"][0]["value"], 100.0);
+    assert_eq!(json[0]["metrics"][0]["interval.ms"], 10_000);
+}
+
+#[test]
+fn normalize_absolute_counters() {
+    let reading = |value, seconds| {
+        Metric::new(
+            "my_counter",
+            MetricKind::Absolute,
+            MetricValue::Counter { value },
+        )
+        .with_timestamp(Some(Utc.timestamp_opt(1_692_000_000 + seconds, 0).unwrap()))
+        .with_tags(Some(metric_tags!("code" => "200")))
+    };
+
+    // Absolute counters are kept as they are by default
+    let mut normalizer = MetricNormalizer::from(NewRelicMetricsNormalizer::default());
+    let metric = normalizer.normalize(reading(100.0, 0)).unwrap();
+    assert_eq!(metric.kind(), MetricKind::Absolute);
+
+    let mut normalizer = MetricNormalizer::from(NewRelicMetricsNormalizer::new(true, 3600));
+    assert!(normalizer.normalize(reading(100.0, 0)).is_none());
+    let metric = normalizer.normalize(reading(130.0, 10)).unwrap();
+
+    assert_eq!(metric.kind(), MetricKind::Incremental);
+    assert_eq!(metric.value(), &MetricValue::Counter { value: 30.0 });
+    assert_eq!(metric.interval_ms(), NonZeroU32::new(10_000));
+
+    let model = MetricsApiModel::try_from(vec![Event::Metric(metric)])
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+
+    assert_eq!(
+        metrics[0].get("type").unwrap().to_string_lossy(),
+        "count".to_owned()
+    );
+    assert_eq!(metrics[0].get("value").unwrap(), &Value::from(30.0));
+    assert_eq!(metrics[0].get("interval.ms").unwrap(), &Value::from(10_000));
+}
+
+#[test]
+fn normalize_absolute_counters_expired() {
+    let reading = |code, value, seconds| {
+        Metric::new(
+            "my_counter",
+            MetricKind::Absolute,
+            MetricValue::Counter { value },
+        )
+        .with_timestamp(Some(Utc.timestamp_opt(1_692_000_000 + seconds, 0).unwrap()))
+        .with_tags(Some(metric_tags!("code" => code)))
+    };
+    let mut normalizer = MetricNormalizer::from(NewRelicMetricsNormalizer::new(true, 60));
+
+    assert!(normalizer.normalize(reading("200", 100.0, 0)).is_none());
+    assert!(normalizer.normalize(reading("500", 10.0, 0)).is_none());
+    assert!(normalizer.normalize(reading("200", 130.0, 30)).is_some());
+    assert_eq!(normalizer.get_state_mut().len(), 2);
+
+    // The series of 500s stopped being reported, so its state is released
+    assert!(normalizer.normalize(reading("200", 160.0, 70)).is_some());
+    assert_eq!(normalizer.get_state_mut().len(), 1);
+
+    // A series that comes back is only used as a reference again
+    assert!(normalizer.normalize(reading("500", 20.0, 80)).is_none());
+    let metric = normalizer.normalize(reading("500", 25.0, 90)).unwrap();
+
+    assert_eq!(metric.value(), &MetricValue::Counter { value: 5.0 });
+    assert_eq!(metric.interval_ms(), NonZeroU32::new(10_000));
+}
+
+#[test]
+fn resolve_api_uris() {
+    let uri = |api, region, endpoint: Option<&str>| {
+        let config = NewRelicConfig {
+            api,
+            region,
+            account_id: "xxxx".to_owned().into(),
+            endpoint: endpoint.map(|endpoint| endpoint.parse().unwrap()),
+            ..Default::default()
+        };
+        NewRelicCredentials::from(&config).get_uri().to_string()
+    };
+
+    assert_eq!(
+        uri(NewRelicApi::Events, None, None),
+        "https://insights-collector.newrelic.com/v1/accounts/xxxx/events"
+    );
+    assert_eq!(
+        uri(NewRelicApi::Metrics, Some(NewRelicRegion::Us), None),
+        "https://metric-api.newrelic.com/metric/v1"
+    );
+    assert_eq!(
+        uri(NewRelicApi::Logs, Some(NewRelicRegion::Us), None),
+        "https://log-api.newrelic.com/log/v1"
+    );
+    assert_eq!(
+        uri(NewRelicApi::Events, Some(NewRelicRegion::Eu), None),
+        "https://insights-collector.eu01.nr-data.net/v1/accounts/xxxx/events"
+    );
+    assert_eq!(
+        uri(NewRelicApi::Metrics, Some(NewRelicRegion::Eu), None),
+        "https://metric-api.eu.newrelic.com/metric/v1"
+    );
+    assert_eq!(
+        uri(NewRelicApi::Logs, Some(NewRelicRegion::Eu), None),
+        "https://log-api.eu.newrelic.com/log/v1"
+    );
+
+    // The endpoint takes precedence over the region
+    assert_eq!(
+        uri(
+            NewRelicApi::Logs,
+            Some(NewRelicRegion::Eu),
+            Some("https://example.com/log/v1")
+        ),
+        "https://example.com/log/v1"
+    );
+}
+
+#[test]
+fn retry_action_for_status() {
+    for (status, retry, successful) in [
+        (200, false, true),
+        (202, false, true),
+        (400, false, false),
+        (403, false, false),
+        (413, false, false),
+        (429, true, false),
+        (500, true, false),
+        (501, false, false),
+        (502, true, false),
+        (503, true, false),
+    ] {
+        let status = http::StatusCode::from_u16(status).unwrap();
+        let action = NewRelicApiRetry::action_for_status(status);
+        assert_eq!(action.is_retryable(), retry, "{}", status);
+        assert_eq!(action.is_successful(), successful, "{}", status);
+        assert_eq!(
+            action.is_not_retryable(),
+            !retry && !successful,
+            "{}",
+            status
+        );
+    }
+}
+
+/// Sends a request holding `num_logs` logs to a server that responds to the requests it receives
+/// with `statuses`, in order, returning the response and the number of logs in each request.
+async fn send_halved_request(
+    num_logs: usize,
+    statuses: &'static [u16],
+) -> (NewRelicApiResponse, Vec<usize>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let handler_received = Arc::clone(&received);
+    let endpoint = spawn_blackhole_http_server(move |request: http::Request<hyper::Body>| {
+        let received = Arc::clone(&handler_received);
+        async move {
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let model: LogsApiModel = serde_json::from_slice(&body).unwrap();
+            let mut received = received.lock().unwrap();
+            received.push(model.0[0].get("logs").map_or(0, Vec::len));
+
+            let status = statuses[(received.len() - 1).min(statuses.len() - 1)];
+            Ok(http::Response::builder()
+                .status(status)
+                .body(hyper::Body::empty())
+                .unwrap())
+        }
+    })
+    .await;
+
+    let events = (0..num_logs)
+        .map(|i| Event::Log(LogEvent::from(format!("message {}", i))))
+        .collect();
+    let model = NewRelicApiModel::Logs(
+        LogsApiModel::try_from(events).expect("Failed mapping logs into API model"),
+    );
+    let request = NewRelicApiRequest {
+        metadata: Default::default(),
+        finalizers: Default::default(),
+        credentials: Arc::new(NewRelicCredentials {
+            license_key: "xxxx".to_owned(),
+            account_id: "xxxx".to_owned(),
+            api: NewRelicApi::Logs,
+            region: NewRelicRegion::Us,
+            override_uri: Some(endpoint),
+        }),
+        payload: model_to_json(&model).unwrap().into(),
+        model: Some(model),
+        compression: Compression::None,
+    };
+    let mut service = NewRelicApiService {
+        client: HttpClient::new(None, &ProxyConfig::default()).unwrap(),
+        // Halves are retried twice
+        half_retry_policy: FixedRetryPolicy::new(
+            2,
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+            NewRelicApiRetry,
+        ),
+    };
+
+    let response = service.call(request).await.expect("Request failed");
+    let received = received.lock().unwrap().clone();
+    (response, received)
+}
+
+#[tokio::test]
+async fn halve_payload_too_large() {
+    // Only the first, full size payload is too large
+    let (response, received) = send_halved_request(4, &[413, 200]).await;
+
+    assert_eq!(received, vec![4, 2, 2]);
+    assert_eq!(response.status_code(), http::StatusCode::OK);
+    assert!(response.halved());
+    assert_eq!(response.event_status(), EventStatus::Delivered);
+    assert!(NewRelicApiRetry
+        .should_retry_response(&response)
+        .is_successful());
+}
+
+#[tokio::test]
+async fn halve_payload_too_large_half_retried() {
+    // The first half is unavailable once, and is retried on its own
+    let (response, received) = send_halved_request(4, &[413, 503, 200]).await;
+
+    assert_eq!(received, vec![4, 2, 2, 2]);
+    assert_eq!(response.status_code(), http::StatusCode::OK);
+    assert!(response.halved());
+    assert_eq!(response.event_status(), EventStatus::Delivered);
+    assert!(NewRelicApiRetry
+        .should_retry_response(&response)
+        .is_successful());
+}
+
+#[tokio::test]
+async fn halve_payload_too_large_half_failed() {
+    // The first half is delivered, so the second one failing can't retry the whole payload, even
+    // once the retries of the half are exhausted
+    let (response, received) = send_halved_request(4, &[413, 200, 500]).await;
+
+    assert_eq!(received, vec![4, 2, 2, 2, 2]);
+    assert_eq!(
+        response.status_code(),
+        http::StatusCode::INTERNAL_SERVER_ERROR
+    );
+    assert_eq!(response.event_status(), EventStatus::Errored);
+    assert!(NewRelicApiRetry
+        .should_retry_response(&response)
+        .is_not_retryable());
+
+    // A half that can't be delivered isn't retried, and rejects the payload
+    let (response, received) = send_halved_request(4, &[413, 200, 400]).await;
+
+    assert_eq!(received, vec![4, 2, 2]);
+    assert_eq!(response.status_code(), http::StatusCode::BAD_REQUEST);
+    assert_eq!(response.event_status(), EventStatus::Rejected);
+    assert!(NewRelicApiRetry
+        .should_retry_response(&response)
+        .is_not_retryable());
+}
+
+#[tokio::test]
+async fn halve_payload_too_large_halves_too_large() {
+    // Every log is too large by itself, so none of them is delivered
+    let (response, received) = send_halved_request(2, &[413]).await;
+
+    assert_eq!(received, vec![2, 1, 1]);
+    assert_eq!(response.status_code(), http::StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.event_status(), EventStatus::Rejected);
+    assert!(NewRelicApiRetry
+        .should_retry_response(&response)
+        .is_not_retryable());
+
+    // A single log that is too large isn't halved
+    let (response, received) = send_halved_request(1, &[413]).await;
+
+    assert_eq!(received, vec![1]);
+    assert!(!response.halved());
+    assert_eq!(response.event_status(), EventStatus::Rejected);
+}
+
+#[test]
+fn generate_event_api_model_timestamp() {
+    // With a timestamp
+    let mut log = LogEvent::from("This is a message");
+    log.insert(
+        "timestamp",
+        Value::Timestamp(Utc.timestamp_millis_opt(1_692_000_000_500).unwrap()),
+    );
+    let model = EventsApiModel::try_from(vec![Event::Log(log)])
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("timestamp").unwrap(),
+        &Value::from(1_692_000_000_500_i64)
+    );
+
+    // With a timestamp in another field
+    let mut log = LogEvent::from("This is a message");
+    log.insert(
+        "time",
+        Value::Timestamp(Utc.timestamp_millis_opt(1_692_000_000_500).unwrap()),
+    );
+    let config = NewRelicEventsConfig {
+        timestamp_field: "time".into(),
+        ..Default::default()
+    };
+    let model =
+        EventsApiModel::try_from_events(vec![Event::Log(log)], &config, &Default::default())
+            .expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("timestamp").unwrap(),
+        &Value::from(1_692_000_000_500_i64)
+    );
+
+    // With an integer timestamp, in seconds, milliseconds, microseconds, or nanoseconds
+    for (timestamp, expected) in [
+        (1_692_000_000_i64, 1_692_000_000_000_i64),
+        (1_692_000_000_500, 1_692_000_000_500),
+        (1_692_000_000_500_000, 1_692_000_000_500),
+        (1_692_000_000_500_000_000, 1_692_000_000_500),
+    ] {
+        let mut log = LogEvent::from("This is a message");
+        log.insert("timestamp", timestamp);
+        let model = EventsApiModel::try_from(vec![Event::Log(log)])
+            .expect("Failed mapping events into API model");
+
+        assert_eq!(
+            model.0[0].get("timestamp").unwrap(),
+            &Value::from(expected),
+            "{}",
+            timestamp
+        );
+    }
+
+    // Without a timestamp, the current time is used
+    let before = Utc::now().timestamp_millis();
+    let event = Event::Log(LogEvent::from("This is a message"));
+    let model =
+        EventsApiModel::try_from(vec![event]).expect("Failed mapping events into API model");
+    let timestamp = model.0[0].get("timestamp").unwrap().as_integer().unwrap();
+
+    assert!(timestamp >= before);
+    assert!(timestamp <= Utc::now().timestamp_millis());
+}
+
+#[test]
+fn generate_log_api_model_level() {
+    let mut log = LogEvent::from("This is a message");
+    log.insert("severity", "warning");
+    let event = Event::Log(log);
+
+    // The severity is kept as it is by default
+    let model =
+        LogsApiModel::try_from(vec![event.clone()]).expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("severity").unwrap(), &Value::from("warning"));
+    assert!(logs[0].get("level").is_none());
+
+    let config = NewRelicLogsConfig {
+        level_field: Some("severity".into()),
+        uppercase_level: true,
+        ..Default::default()
+    };
+    let model = LogsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("level").unwrap(), &Value::from("WARNING"));
+    assert!(logs[0].get("severity").is_none());
+}
+
+#[test]
+fn generate_log_api_model_trace_ids() {
+    let mut log = LogEvent::from("This is a message");
+    log.insert("dd.trace_id", "4bf92f3577b34da6");
+    log.insert("dd.span_id", "00f067aa0ba902b7");
+    let event = Event::Log(log);
+
+    let config = NewRelicLogsConfig {
+        trace_id_field: Some("dd.trace_id".into()),
+        span_id_field: Some("dd.span_id".into()),
+        ..Default::default()
+    };
+    let model = LogsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(
+        logs[0].get("trace.id").unwrap(),
+        &Value::from("4bf92f3577b34da6")
+    );
+    assert_eq!(
+        logs[0].get("span.id").unwrap(),
+        &Value::from("00f067aa0ba902b7")
+    );
+    assert!(logs[0].get("dd.trace_id").is_none());
+    assert!(logs[0].get("dd.span_id").is_none());
+}
+
+#[test]
+fn generate_log_api_model_attribute_prefix() {
+    let mut log = LogEvent::from("This is a message");
+    log.insert(
+        "timestamp",
+        Utc.timestamp_millis_opt(1_692_000_000_500).unwrap(),
+    );
+    log.insert("user_id", 123456);
+    let event = Event::Log(log);
+
+    let config = NewRelicLogsConfig {
+        attribute_prefix: Some("custom.".to_owned()),
+        ..Default::default()
+    };
+    let model = LogsApiModel::try_from_events(vec![event], &config, &Default::default())
+        .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("custom.user_id").unwrap(), &Value::from(123456));
+    assert!(logs[0].get("user_id").is_none());
+    assert_eq!(
+        logs[0].get("message").unwrap(),
+        &Value::from("This is a message")
+    );
+    assert!(logs[0].get("timestamp").is_some());
+    assert_eq!(logs[0].len(), 3);
+}
+
+#[test]
+fn generate_api_models_binary_encoding() {
+    let binary = || Value::Bytes(bytes::Bytes::from_static(b"\xff\xfe\x01"));
+    let attributes = NewRelicAttributesConfig {
+        binary_encoding: NewRelicBinaryEncoding::Base64,
+        ..Default::default()
+    };
+
+    let metadata = EventMetadata::default_with_value(Value::from(BTreeMap::from([(
+        "payload".to_owned(),
+        binary(),
+    )])));
+    let event = Event::Metric(Metric::new_with_metadata(
+        "my_metric",
+        MetricKind::Absolute,
+        MetricValue::Gauge { value: 100.0 },
+        metadata,
+    ));
+    let config = NewRelicMetricsConfig {
+        metadata_attributes: true,
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(vec![event], &config, &attributes)
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(json[0]["metrics"][0]["attributes"]["payload"], "//4B");
+
+    let mut log = LogEvent::from("This is a message");
+    log.insert("payload", binary());
+    let model = EventsApiModel::try_from_events(
+        vec![Event::Log(log.clone())],
+        &Default::default(),
+        &attributes,
+    )
+    .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("payload").unwrap(), &Value::from("//4B"));
+
+    let model = LogsApiModel::try_from_events(
+        vec![Event::Log(log.clone())],
+        &Default::default(),
+        &attributes,
+    )
+    .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("payload").unwrap(), &Value::from("//4B"));
+
+    // By default, invalid sequences are replaced
+    let model =
+        LogsApiModel::try_from(vec![Event::Log(log)]).expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(
+        logs[0].get("payload").unwrap(),
+        &Value::from("\u{fffd}\u{fffd}\u{1}")
+    );
+}
+
+#[test]
+fn generate_api_models_max_value_bytes() {
+    let stack_trace = "at my_function (src/main.rs:42)\n".repeat(160);
+    assert!(stack_trace.len() > 5_000);
+    let attributes = NewRelicAttributesConfig {
+        max_value_bytes: Some(4096),
+        ..Default::default()
+    };
+    let assert_truncated = |value: &Value| {
+        let value = value.as_str().expect("Value is not a string");
+        assert_eq!(value.len(), 4096);
+        assert!(value.ends_with("..."));
+        assert!(stack_trace.starts_with(value.trim_end_matches("...")));
+    };
+
+    let event = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_tags(Some(metric_tags!("stack_trace" => stack_trace.clone()))),
+    );
+    let model = MetricsApiModel::try_from_events(vec![event], &Default::default(), &attributes)
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+    let metric_attributes = metrics[0]
+        .get("attributes")
+        .and_then(Value::as_object)
+        .expect("Attributes not present");
+
+    assert_truncated(metric_attributes.get("stack_trace").unwrap());
+
+    let mut log = LogEvent::from("This is a message");
+    log.insert("stack_trace", stack_trace.clone());
+    let model = EventsApiModel::try_from_events(
+        vec![Event::Log(log.clone())],
+        &Default::default(),
+        &attributes,
+    )
+    .expect("Failed mapping events into API model");
+
+    assert_truncated(model.0[0].get("stack_trace").unwrap());
+    assert_eq!(
+        model.0[0].get("message").unwrap(),
+        &Value::from("This is a message")
+    );
+
+    let model =
+        LogsApiModel::try_from_events(vec![Event::Log(log)], &Default::default(), &attributes)
+            .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_truncated(logs[0].get("stack_trace").unwrap());
+}
+
+#[test]
+fn generate_api_models_max_value_bytes_invalid_utf8() {
+    let attributes = NewRelicAttributesConfig {
+        max_value_bytes: Some(8),
+        ..Default::default()
+    };
+    let mut log = LogEvent::from("This is a message");
+    // Each invalid byte is sent as a 3 bytes replacement character
+    log.insert(
+        "payload",
+        bytes::Bytes::from_static(&[0xff, 0xff, 0xff, 0xff]),
+    );
+    let model =
+        LogsApiModel::try_from_events(vec![Event::Log(log)], &Default::default(), &attributes)
+            .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("payload").unwrap(), &Value::from("\u{fffd}..."));
+}
+
+#[tokio::test]
+async fn max_value_bytes_shorter_than_marker() {
+    let mut config = NewRelicConfig::default();
+    config.attributes.max_value_bytes = Some(2);
+
+    assert!(config.build(SinkContext::default()).await.is_err());
+}
+
+#[test]
+fn generate_api_models_attribute_transformers() {
+    let log = || {
+        let mut log = LogEvent::default();
+        log.insert("message", "  This is a message  ");
+        log.insert("Level", " err ");
+        log.insert("User", "Joe");
+        Event::Log(log)
+    };
+    let rename_level = |key: &str| {
+        NewRelicAttributeTransformer::RenameMap(NewRelicRenameMapConfig {
+            key: key.to_owned(),
+            values: BTreeMap::from([("err".to_owned(), "error".to_owned())]),
+        })
+    };
+    let transform_log = |transformers: Vec<NewRelicAttributeTransformer>| {
+        let attributes = NewRelicAttributesConfig {
+            attribute_transformers: transformers,
+            ..Default::default()
+        };
+        let model = LogsApiModel::try_from_events(vec![log()], &Default::default(), &attributes)
+            .expect("Failed mapping logs into API model");
+        serde_json::to_value(&model).expect("Failed serializing API model")[0]["logs"][0].clone()
+    };
+
+    // The reserved `message` is left alone
+    assert_eq!(
+        transform_log(vec![NewRelicAttributeTransformer::LowercaseKeys]),
+        serde_json::json!({"message": "  This is a message  ", "level": " err ", "user": "Joe"})
+    );
+    assert_eq!(
+        transform_log(vec![NewRelicAttributeTransformer::TrimValues]),
+        serde_json::json!({"message": "  This is a message  ", "Level": "err", "User": "Joe"})
+    );
+    assert_eq!(
+        transform_log(vec![rename_level("Level")]),
+        serde_json::json!({"message": "  This is a message  ", "Level": " err ", "User": "Joe"})
+    );
+
+    // Transformers are applied in order, each to the output of the previous one
+    assert_eq!(
+        transform_log(vec![
+            NewRelicAttributeTransformer::LowercaseKeys,
+            NewRelicAttributeTransformer::TrimValues,
+            rename_level("level"),
+        ]),
+        serde_json::json!({"message": "  This is a message  ", "level": "error", "user": "Joe"})
+    );
+    assert_eq!(
+        transform_log(vec![
+            rename_level("level"),
+            NewRelicAttributeTransformer::LowercaseKeys,
+            NewRelicAttributeTransformer::TrimValues,
+        ]),
+        serde_json::json!({"message": "  This is a message  ", "level": "err", "user": "Joe"})
+    );
+
+    // The attributes of metrics and events are transformed as well
+    let attributes = NewRelicAttributesConfig {
+        attribute_transformers: vec![
+            NewRelicAttributeTransformer::LowercaseKeys,
+            NewRelicAttributeTransformer::TrimValues,
+        ],
+        ..Default::default()
+    };
+    let metric = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_tags(Some(metric_tags!("Env" => " prod "))),
+    );
+    let model = MetricsApiModel::try_from_events(vec![metric], &Default::default(), &attributes)
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({"env": "prod"})
+    );
+
+    // The attributes the sink sets on metrics are left alone
+    let metric = Event::Metric(
+        Metric::new(
+            "my_counter",
+            MetricKind::Incremental,
+            MetricValue::Counter { value: 5.0 },
+        )
+        .with_tags(Some(
+            metric_tags!("sample_rate" => "0.1", "Env" => " prod "),
+        ))
+        .with_interval_ms(NonZeroU32::new(1000)),
+    );
+    let config = NewRelicMetricsConfig {
+        sample_rate_policy: NewRelicSampleRatePolicy::Attribute,
+        metric_kind_attribute: true,
+        default_unit: Some("bytes".to_owned()),
+        ..Default::default()
+    };
+    let model = MetricsApiModel::try_from_events(vec![metric], &config, &attributes)
+        .expect("Failed mapping metrics into API model");
+    let json = serde_json::to_value(&model).expect("Failed serializing API model");
+
+    assert_eq!(
+        json[0]["metrics"][0]["attributes"],
+        serde_json::json!({
+            "env": "prod",
+            "sampleRate": 0.1,
+            "unit": "bytes",
+            "vector.metric_kind": "incremental",
+        })
+    );
+
+    let mut event = LogEvent::default();
+    event.insert("eventType", "MyEvent");
+    event.insert("User", " Joe ");
+    let model =
+        EventsApiModel::try_from_events(vec![Event::Log(event)], &Default::default(), &attributes)
+            .expect("Failed mapping events into API model");
+
+    assert_eq!(
+        model.0[0].get("eventType").unwrap(),
+        &Value::from("MyEvent")
+    );
+    assert_eq!(model.0[0].get("user").unwrap(), &Value::from("Joe"));
+}
+
+#[test]
+fn generate_api_models_default_attributes() {
+    let attributes = NewRelicAttributesConfig {
+        default_attributes: BTreeMap::from([
+            ("env".to_owned(), "production".to_owned()),
+            ("team".to_owned(), "observability".to_owned()),
+        ]),
+        ..Default::default()
+    };
+
+    // Tags take precedence over the default attributes
+    let event = Event::Metric(
+        Metric::new(
+            "my_metric",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 100.0 },
+        )
+        .with_tags(Some(metric_tags!("env" => "staging"))),
+    );
+    let model = MetricsApiModel::try_from_events(vec![event], &Default::default(), &attributes)
+        .expect("Failed mapping metrics into API model");
+    let metrics = model.0[0]
+        .get("metrics")
+        .expect("Metric data store not present");
+    let metric_attributes = metrics[0]
+        .get("attributes")
+        .and_then(Value::as_object)
+        .expect("Attributes not present");
+
+    assert_eq!(
+        metric_attributes.get("env").unwrap(),
+        &Value::from("staging")
+    );
+    assert_eq!(
+        metric_attributes.get("team").unwrap(),
+        &Value::from("observability")
+    );
+
+    // And so do the fields of logs and events
+    let mut log = LogEvent::from("This is a message");
+    log.insert("env", "staging");
+    let event = Event::Log(log);
+    let model =
+        LogsApiModel::try_from_events(vec![event.clone()], &Default::default(), &attributes)
+            .expect("Failed mapping logs into API model");
+    let logs = model.0[0].get("logs").expect("Logs data store not present");
+
+    assert_eq!(logs[0].get("env").unwrap(), &Value::from("staging"));
+    assert_eq!(logs[0].get("team").unwrap(), &Value::from("observability"));
+
+    let model = EventsApiModel::try_from_events(vec![event], &Default::default(), &attributes)
+        .expect("Failed mapping events into API model");
+
+    assert_eq!(model.0[0].get("env").unwrap(), &Value::from("staging"));
+    assert_eq!(
+        model.0[0].get("team").unwrap(),
+        &Value::from("observability")
+    );
+}
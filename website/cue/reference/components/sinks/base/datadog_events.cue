@@ -27,6 +27,110 @@ base: components: sinks: datadog_events: configuration: {
 			type: bool: {}
 		}
 	}
+	additional_fields: {
+		description: """
+			Fields sent to Datadog, in addition to the ones the Events API defines.
+
+			Any other field is removed from events before they are sent.
+			"""
+		required: false
+		type: array: {
+			default: []
+			items: type: string: examples: ["custom_attribute", "team.name"]
+		}
+	}
+	api_key_field: {
+		description: """
+			The field holding the Datadog API key of events.
+
+			When present, it takes precedence over the API key set in the metadata of events, and over
+			`default_api_key`.
+			"""
+		required: false
+		type: string: {
+			default: "_dd_api_key"
+			examples: ["dd_api_key"]
+		}
+	}
+	api_version: {
+		description: "Datadog Events API version."
+		required:    false
+		type: string: {
+			default: "v1"
+			enum: {
+				v1: "Events are sent to the `/api/v1/events` endpoint, one event per request."
+				v2: "Events are sent to the `/api/v2/events` endpoint, in batches encoded as a JSON array."
+			}
+		}
+	}
+	batch: {
+		description: """
+			Event batching behavior.
+
+			Only used with version `v2` of the API, as version `v1` accepts a single event per request.
+			"""
+		required: false
+		type: object: options: {
+			max_bytes: {
+				description: """
+					The maximum size of a batch that is processed by a sink.
+
+					This is based on the uncompressed size of the batched events, before they are
+					serialized/compressed.
+					"""
+				required: false
+				type: uint: {
+					default: 1000000
+					unit:    "bytes"
+				}
+			}
+			max_events: {
+				description: "The maximum size of a batch before it is flushed."
+				required:    false
+				type: uint: {
+					default: 100
+					unit:    "events"
+				}
+			}
+			timeout_secs: {
+				description: "The maximum age of a batch before it is flushed."
+				required:    false
+				type: float: {
+					default: 1.0
+					unit:    "seconds"
+				}
+			}
+		}
+	}
+	compression: {
+		description: """
+			Compression configuration.
+
+			All compression algorithms use the default compression level unless otherwise specified.
+			"""
+		required: false
+		type: string: {
+			default: "none"
+			enum: {
+				gzip: """
+					[Gzip][gzip] compression.
+
+					[gzip]: https://www.gzip.org/
+					"""
+				none: "No compression."
+				zlib: """
+					[Zlib][zlib] compression.
+
+					[zlib]: https://zlib.net/
+					"""
+				zstd: """
+					[Zstandard][zstd] compression.
+
+					[zstd]: https://facebook.github.io/zstd/
+					"""
+			}
+		}
+	}
 	default_api_key: {
 		description: """
 			The default Datadog [API key][api_key] to use in authentication of HTTP requests.
@@ -39,6 +143,54 @@ base: components: sinks: datadog_events: configuration: {
 		required: true
 		type: string: examples: ["${DATADOG_API_KEY_ENV_VAR}", "ef8d5de700e7989468166c40fc8a0ccd"]
 	}
+	default_host: {
+		description: "The `host` set on events that don't have one."
+		required:    false
+		type: string: examples: ["my-host"]
+	}
+	default_priority: {
+		description: "The `priority` set on events whose `priority` isn't one the Datadog Events API accepts."
+		required:    false
+		type: string: {
+			default: "normal"
+			enum: {
+				low:    "Low priority."
+				normal: "Normal priority."
+			}
+		}
+	}
+	default_source_type_name: {
+		description: "The `source_type_name` set on events that don't have one."
+		required:    false
+		type: string: examples: ["vector"]
+	}
+	default_text: {
+		description: """
+			The `text` set on events that have neither a `text` nor a message, when
+			`missing_field_policy` is `fill`.
+			"""
+		required: false
+		type: string: {
+			default: "No text"
+			examples: ["(no text)"]
+		}
+	}
+	default_title: {
+		description: "The `title` set on events that don't have one, when `missing_field_policy` is `fill`."
+		required:    false
+		type: string: {
+			default: "Vector event"
+			examples: ["Deployment"]
+		}
+	}
+	default_to_hostname: {
+		description: """
+			Whether events that don't have a `host` get the hostname of the machine Vector runs on,
+			when `default_host` isn't set.
+			"""
+		required: false
+		type: bool: default: false
+	}
 	endpoint: {
 		description: """
 			The endpoint to send observability data to.
@@ -52,6 +204,73 @@ base: components: sinks: datadog_events: configuration: {
 		required: false
 		type: string: examples: ["http://127.0.0.1:8080", "http://example.com:12345"]
 	}
+	markdown: {
+		description: "Whether to wrap the `text` of events in `%%%` markers, for Datadog to render it as Markdown."
+		required:    false
+		type: bool: default: false
+	}
+	max_aggregation_key_length: {
+		description: """
+			The maximum length of the `aggregation_key` of events, in characters.
+
+			Longer keys are truncated, as Datadog doesn't accept them.
+			"""
+		required: false
+		type: uint: default: 100
+	}
+	max_events_per_request: {
+		description: """
+			The maximum number of events sent in a single request, with version `v2` of the API.
+
+			Batches of more events are split into several requests.
+			"""
+		required: false
+		type: uint: {
+			examples: [100]
+			unit: "events"
+		}
+	}
+	max_payload_bytes: {
+		description: """
+			The maximum size of the encoded payload of a request, before compression.
+
+			Requests over it are dropped. With version `v1` of the API, requests carry a single event,
+			so this limits the size of events.
+			"""
+		required: false
+		type: uint: {
+			examples: [65536]
+			unit: "bytes"
+		}
+	}
+	min_compression_bytes: {
+		description: """
+			The minimum size of the encoded payload of a request for it to be compressed.
+
+			Smaller requests are sent uncompressed, even when `compression` is enabled, as compressing
+			them costs more than it saves.
+			"""
+		required: false
+		type: uint: {
+			examples: [1024]
+			unit: "bytes"
+		}
+	}
+	missing_field_policy: {
+		description: """
+			How events missing a `title` or `text` are handled.
+
+			The Datadog Events API rejects events without them.
+			"""
+		required: false
+		type: string: {
+			default: "drop"
+			enum: {
+				drop: "Events are dropped."
+				fill: "The missing fields are set to `default_title` and `default_text`."
+			}
+		}
+	}
 	region: {
 		deprecated:         true
 		deprecated_message: "This option has been deprecated, use the `site` option instead."
@@ -62,6 +281,27 @@ base: components: sinks: datadog_events: configuration: {
 			us: "US region."
 		}
 	}
+	rename_fields: {
+		description: """
+			Fields renamed before the sink completes the fields of events and removes the ones that
+			aren't sent to Datadog, such as to send a `subject` field as the `title` of events.
+
+			Fields and their new names are paths, so `meta.title` renames the `title` field nested in
+			`meta`. A field isn't renamed if the event already has a field with its new name.
+			"""
+		required: false
+		type: object: {
+			examples: [{
+				description: "text"
+				subject:     "title"
+			}]
+			options: "*": {
+				description: "A field and its new name."
+				required:    true
+				type: string: {}
+			}
+		}
+	}
 	request: {
 		description: """
 			Middleware settings for outbound requests.
@@ -221,6 +461,21 @@ base: components: sinks: datadog_events: configuration: {
 			}
 		}
 	}
+	serializer: {
+		description: "The serialization format of the body of requests."
+		required:    false
+		type: string: {
+			default: "json"
+			enum: {
+				json: "Events are serialized as JSON."
+				msgpack: """
+					Events are serialized as [MessagePack][msgpack].
+
+					[msgpack]: https://msgpack.org/
+					"""
+			}
+		}
+	}
 	site: {
 		description: """
 			The Datadog [site][dd_site] to send observability data to.
@@ -233,6 +488,16 @@ base: components: sinks: datadog_events: configuration: {
 			examples: ["us3.datadoghq.com", "datadoghq.eu"]
 		}
 	}
+	timestamp_field: {
+		description: """
+			The field holding the timestamp of events, which is sent as their `date_happened` when they
+			don't have one.
+
+			By default, the field with the `timestamp` meaning is used.
+			"""
+		required: false
+		type: string: examples: ["time"]
+	}
 	tls: {
 		description: "Configures the TLS options for incoming/outgoing connections."
 		required:    false
@@ -328,4 +593,20 @@ base: components: sinks: datadog_events: configuration: {
 			}
 		}
 	}
+	unknown_field_policy: {
+		description: """
+			How events with fields that aren't sent to Datadog are handled.
+
+			Only the fields the Datadog Events API defines, and `additional_fields`, are sent.
+			"""
+		required: false
+		type: string: {
+			default: "strip"
+			enum: {
+				reject: "Events are dropped, and a warning naming the fields is logged."
+				strip:  "The fields are removed from events."
+				warn:   "The fields are removed from events, and a warning naming them is logged."
+			}
+		}
+	}
 }
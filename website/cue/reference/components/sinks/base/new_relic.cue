@@ -41,6 +41,52 @@ base: components: sinks: new_relic: configuration: {
 			metrics: "Metrics API."
 		}
 	}
+	attribute_transformers: {
+		description: """
+			Transformations applied, in order, to the attributes of all data.
+
+			The fields New Relic reserves, such as the `eventType` of events and the `message` of logs,
+			aren't transformed, nor are the `unit`, `sampleRate`, and `vector.metric_kind` attributes
+			the sink sets on metrics.
+			"""
+		required: false
+		type: array: {
+			default: []
+			items: type: object: options: {
+				key: {
+					description:   "The key of the attribute whose values are replaced."
+					relevant_when: "type = \"rename_map\""
+					required:      true
+					type: string: examples: ["level"]
+				}
+				type: {
+					description: "The transformation applied to attributes."
+					required:    true
+					type: string: enum: {
+						lowercase_keys: "Lowercase the keys of attributes."
+						rename_map:     "Replace the values of an attribute, such as `err` with `error`."
+						trim_values:    "Trim the whitespace around the values of attributes."
+					}
+				}
+				values: {
+					description:   "The values of the attribute, and the values they're replaced with."
+					relevant_when: "type = \"rename_map\""
+					required:      true
+					type: object: {
+						examples: [{
+							err:  "error"
+							warn: "warning"
+						}]
+						options: "*": {
+							description: "A value and its replacement."
+							required:    true
+							type: string: {}
+						}
+					}
+				}
+			}
+		}
+	}
 	batch: {
 		description: "Event batching behavior."
 		required:    false
@@ -76,6 +122,17 @@ base: components: sinks: new_relic: configuration: {
 			}
 		}
 	}
+	binary_encoding: {
+		description: "How to encode the values of attributes that aren't valid UTF-8, as New Relic only accepts text."
+		required:    false
+		type: string: {
+			default: "lossy_utf8"
+			enum: {
+				base64:     "Encode the whole value as standard Base64."
+				lossy_utf8: "Replace the invalid sequences with the `U+FFFD` replacement character."
+			}
+		}
+	}
 	compression: {
 		description: """
 			Compression configuration.
@@ -105,6 +162,29 @@ base: components: sinks: new_relic: configuration: {
 			}
 		}
 	}
+	default_attributes: {
+		description: """
+			Attributes set on all data, unless it already has an attribute with the same key, such as a
+			tag of a metric.
+			"""
+		required: false
+		type: object: {
+			examples: [{
+				env:  "production"
+				team: "observability"
+			}]
+			options: "*": {
+				description: "An attribute and its value."
+				required:    true
+				type: string: {}
+			}
+		}
+	}
+	default_host: {
+		description: "The `host` attribute set on data that doesn't have one."
+		required:    false
+		type: string: examples: ["${HOSTNAME}", "my-host"]
+	}
 	encoding: {
 		description: "Transformations to prepare an event for serialization."
 		required:    false
@@ -129,11 +209,461 @@ base: components: sinks: new_relic: configuration: {
 			}
 		}
 	}
+	endpoint: {
+		description: "The endpoint to send data to, instead of the one of the selected `api` and `region`."
+		required:    false
+		type: string: examples: ["https://metric-api.eu.newrelic.com/metric/v1"]
+	}
+	events: {
+		description: "New Relic Events API configuration."
+		required:    false
+		type: object: options: {
+			default_event_type: {
+				description: "The `eventType` set on events that don't already have one."
+				required:    false
+				type: string: {
+					default: "VectorSink"
+					examples: ["MyPipeline"]
+				}
+			}
+			event_type_field: {
+				description: """
+					The field whose value is copied into the `eventType` of events, which keep the field too.
+
+					Events without it keep their `eventType` field, or get `default_event_type`.
+					"""
+				required: false
+				type: string: examples: ["kind"]
+			}
+			exclude_attributes: {
+				description: """
+					The attributes not sent with events, including those parsed from a JSON `message`.
+
+					Listing an attribute also excludes the attributes nested in it. It takes precedence over
+					`include_attributes`.
+					"""
+				required: false
+				type: array: {
+					default: []
+					items: type: string: examples: ["password", "headers"]
+				}
+			}
+			field_precedence: {
+				description: """
+					Which value is kept when a field of an event and a field of the JSON object in its `message`
+					have the same key.
+					"""
+				required: false
+				type: string: {
+					default: "json_wins"
+					enum: {
+						fields_win: "Keep the field of the event."
+						json_wins:  "Keep the field of the JSON object."
+					}
+				}
+			}
+			include_attributes: {
+				description: """
+					The attributes sent with events, including those parsed from a JSON `message`. When
+					empty, all of them are sent.
+
+					Listing an attribute also includes the attributes nested in it, such as `tags.0` for `tags`.
+					The `eventType` and `timestamp` of events are always sent.
+					"""
+				required: false
+				type: array: {
+					default: []
+					items: type: string: examples: ["user_id", "tags"]
+				}
+			}
+			keep_raw_message: {
+				description: """
+					Whether to keep the `message` of events holding a JSON object, which is otherwise removed
+					once the fields of the object are added to the event.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			nan_policy: {
+				description: "How to handle NaN and infinite values, which New Relic doesn't support."
+				required:    false
+				type: string: {
+					default: "drop"
+					enum: {
+						drop: "Drop the metric, event, or log holding the value."
+						error: """
+							Fail the whole batch holding the value.
+
+							None of the metrics, events, or logs of the batch are sent, including those without NaN or
+							infinite values. They are all rejected and reported as dropped, and the failure is reported
+							as an error of the sink.
+							"""
+						zero: "Replace the value with zero."
+					}
+				}
+			}
+			require_event_type: {
+				description: """
+					Whether to drop events that don't have an `eventType`, instead of setting
+					`default_event_type`.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			timestamp_field: {
+				description: """
+					The field holding the timestamp of events, which is sent in milliseconds since the Unix
+					epoch.
+
+					Integer timestamps are taken as seconds, milliseconds, microseconds, or nanoseconds since
+					the Unix epoch, depending on their magnitude. Events without one are timestamped with the
+					current time.
+					"""
+				required: false
+				type: string: {
+					default: "timestamp"
+					examples: ["time"]
+				}
+			}
+			timestamps_as_millis: {
+				description: """
+					Whether to send the timestamp fields of events in milliseconds since the Unix epoch, instead
+					of as RFC 3339 strings.
+					"""
+				required: false
+				type: bool: default: false
+			}
+		}
+	}
 	license_key: {
 		description: "A valid New Relic license key."
 		required:    true
 		type: string: examples: ["xxxx", "${NEW_RELIC_LICENSE_KEY}"]
 	}
+	logs: {
+		description: "New Relic Logs API configuration."
+		required:    false
+		type: object: options: {
+			attribute_prefix: {
+				description: """
+					A prefix added to the keys of the attributes of logs, such as `custom.`, to avoid clashes
+					with the attributes New Relic reserves.
+
+					The `message`, `timestamp`, `level`, `logtype`, `trace.id`, and `span.id` keys aren't
+					prefixed.
+					"""
+				required: false
+				type: string: examples: ["custom."]
+			}
+			drop_missing_message: {
+				description: "Whether to drop logs that don't have a `message`, instead of setting the placeholder."
+				required:    false
+				type: bool: default: false
+			}
+			level_field: {
+				description: "The field holding the severity of logs, which is sent as their `level`."
+				required:    false
+				type: string: examples: ["severity"]
+			}
+			nan_policy: {
+				description: "How to handle NaN and infinite values, which New Relic doesn't support."
+				required:    false
+				type: string: {
+					default: "drop"
+					enum: {
+						drop: "Drop the metric, event, or log holding the value."
+						error: """
+							Fail the whole batch holding the value.
+
+							None of the metrics, events, or logs of the batch are sent, including those without NaN or
+							infinite values. They are all rejected and reported as dropped, and the failure is reported
+							as an error of the sink.
+							"""
+						zero: "Replace the value with zero."
+					}
+				}
+			}
+			placeholder_message: {
+				description: "The `message` set on logs that don't have one."
+				required:    false
+				type: string: {
+					default: "log from vector"
+					examples: ["(no message)"]
+				}
+			}
+			span_id_field: {
+				description: "The field holding the span ID of logs, which is sent as their `span.id`."
+				required:    false
+				type: string: examples: ["span_id", "dd.span_id"]
+			}
+			trace_id_field: {
+				description: "The field holding the trace ID of logs, which is sent as their `trace.id`."
+				required:    false
+				type: string: examples: ["trace_id", "dd.trace_id"]
+			}
+			uppercase_level: {
+				description: "Whether to uppercase the severity of logs sent as their `level`."
+				required:    false
+				type: bool: default: false
+			}
+			wrapper_key: {
+				description: """
+					The key the logs of requests are wrapped under.
+
+					New Relic expects `logs`, this is only meant for proxies and mock servers that expect
+					another one.
+					"""
+				required: false
+				type: string: {
+					default: "logs"
+					examples: ["data"]
+				}
+			}
+		}
+	}
+	max_payload_bytes: {
+		description: """
+			The maximum size of the JSON payload of a request, before compression.
+
+			Batches whose payload is larger than this are split into several requests. A metric, event,
+			or log larger than this by itself is sent alone.
+			"""
+		required: false
+		type: uint: {
+			default: 999000
+			unit:    "bytes"
+		}
+	}
+	max_value_bytes: {
+		description: """
+			The maximum length of the text values of attributes, in bytes.
+
+			Longer values, such as long stack traces, are truncated and end with `...` to show that
+			they were cut. New Relic truncates values over 4096 bytes itself, without any marker.
+
+			This must be at least 3 bytes, to fit the marker.
+			"""
+		required: false
+		type: uint: {
+			examples: [4096]
+			unit: "bytes"
+		}
+	}
+	metrics: {
+		description: "New Relic Metrics API configuration."
+		required:    false
+		type: object: options: {
+			absolute_counters_ttl_secs: {
+				description: """
+					How long the previous reading of a series is kept for `convert_absolute_counters`, in
+					seconds.
+
+					Series without a reading for longer than this are forgotten, so that series that stop
+					being reported don't hold memory forever. Their next reading is only used as the reference
+					for the one after it again.
+					"""
+				required: false
+				type: uint: {
+					default: 3600
+					unit:    "seconds"
+				}
+			}
+			convert_absolute_counters: {
+				description: """
+					Whether to send absolute counters as counts of their change since the previous reading of
+					their series, instead of as gauges.
+
+					The first reading of a series is only used as the reference for the next one, and isn't
+					sent.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			default_unit: {
+				description: "The `unit` attribute set on metrics whose metadata doesn't have a `unit`."
+				required:    false
+				type: string: examples: ["bytes"]
+			}
+			distribution_quantiles: {
+				description: """
+					The quantiles of distributions sent as separate gauges alongside their summary, named
+					`<name>.p<percentile>`, such as `<name>.p95` for the `0.95` quantile.
+
+					Quantiles must be between 0.0 and 1.0, inclusive.
+					"""
+				required: false
+				type: array: {
+					default: []
+					items: type: float: examples: [0.5, 0.95, 0.99]
+				}
+			}
+			flatten_separator: {
+				description: "The separator used to join the keys of flattened attributes."
+				required:    false
+				type: string: {
+					default: "."
+					examples: ["_"]
+				}
+			}
+			max_attributes: {
+				description: """
+					The maximum number of attributes sent with a metric.
+
+					Metrics with more attributes only keep the first ones, in the order of their keys.
+					"""
+				required: false
+				type: uint: examples: [100]
+			}
+			max_future_secs: {
+				description: """
+					How far in the future the timestamps of metrics can be, as New Relic rejects metrics
+					timestamped too far ahead.
+
+					Later timestamps are replaced with the current time.
+					"""
+				required: false
+				type: uint: {
+					examples: [600]
+					unit: "seconds"
+				}
+			}
+			metadata_attributes: {
+				description: """
+					Whether to add the custom metadata of metrics to their attributes.
+
+					As New Relic only supports scalar attribute values, nested objects and arrays are flattened
+					into separate attributes.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			metric_kind_attribute: {
+				description: """
+					Whether to add the kind of metrics, either `incremental` or `absolute`, to their attributes
+					as `vector.metric_kind`.
+
+					The kind is otherwise lost, as absolute counters are sent as gauges.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			missing_interval_policy: {
+				description: """
+					How to handle incremental metrics without an interval, which New Relic requires for counts and
+					summaries.
+					"""
+				required: false
+				type: string: {
+					default: "drop"
+					enum: {
+						as_gauge:            "Send counters as gauges, which don't need an interval. Other metrics are dropped."
+						drop:                "Drop the metric."
+						synthesize_interval: "Send the metric with an interval of `synthetic_interval_secs`."
+					}
+				}
+			}
+			nan_policy: {
+				description: "How to handle NaN and infinite values, which New Relic doesn't support."
+				required:    false
+				type: string: {
+					default: "drop"
+					enum: {
+						drop: "Drop the metric, event, or log holding the value."
+						error: """
+							Fail the whole batch holding the value.
+
+							None of the metrics, events, or logs of the batch are sent, including those without NaN or
+							infinite values. They are all rejected and reported as dropped, and the failure is reported
+							as an error of the sink.
+							"""
+						zero: "Replace the value with zero."
+					}
+				}
+			}
+			omit_gauge_type: {
+				description: "Whether to send gauges without a `type`, which New Relic then takes for gauges."
+				required:    false
+				type: bool: default: false
+			}
+			prefix_namespace: {
+				description: """
+					Whether to prefix metric names with their namespace, as `<namespace>.<name>`.
+
+					New Relic has no concept of metric namespaces, so without this metrics from different
+					namespaces that share a name are indistinguishable.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			sample_rate_policy: {
+				description: """
+					How to handle the sample rate of counters, taken from the `sample_rate` of their metadata or
+					tags.
+
+					A counter sampled at a rate of `0.1` only counted one in ten occurrences.
+					"""
+				required: false
+				type: string: {
+					default: "ignore"
+					enum: {
+						attribute: "Send the value as it is, with the sample rate as the `sampleRate` attribute."
+						ignore:    "Send the value as it is."
+						scale:     "Divide the value by the sample rate."
+					}
+				}
+			}
+			sanitize_attribute_keys: {
+				description: """
+					Whether to prefix attribute keys that clash with the fields of metrics, such as `timestamp`,
+					with `tag.`.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			sanitize_names: {
+				description: """
+					Whether to replace the characters of metric names that New Relic doesn't accept with `_`,
+					and truncate names to the 255 characters it accepts.
+
+					Only ASCII letters and digits, `.`, `_`, `:`, and `-` are kept.
+					"""
+				required: false
+				type: bool: default: false
+			}
+			synthetic_interval_secs: {
+				description: """
+					The interval sent with incremental metrics that don't have one, when
+					`missing_interval_policy` is `synthesize_interval`.
+					"""
+				required: false
+				type: uint: {
+					default: 60
+					unit:    "seconds"
+				}
+			}
+			value_multiplier: {
+				description: """
+					A factor the values of gauges and counters are multiplied by, such as `1000` to send
+					seconds as milliseconds.
+					"""
+				required: false
+				type: float: examples: [1000.0]
+			}
+			wrapper_key: {
+				description: """
+					The key the metrics of requests are wrapped under.
+
+					New Relic expects `metrics`, this is only meant for proxies and mock servers that expect
+					another one.
+					"""
+				required: false
+				type: string: {
+					default: "metrics"
+					examples: ["data"]
+				}
+			}
+		}
+	}
 	region: {
 		description: "New Relic region."
 		required:    false